@@ -1,63 +1,226 @@
 use crate::app::history::HistoryEntry;
+use crate::cache::{CacheKey, CheckCache};
+use crate::chunking;
 use crate::config::ApiProvider;
-use crate::suggestion::{LlmMatch, LlmResponse, Suggestion};
-use serde_json::json;
+use crate::dialect::{self, Dialect};
+use crate::dictionary;
+use crate::error::GrammyError;
+use crate::language::Lang;
+use crate::prompt::Prompt;
+use crate::provider::{ChatRequest, ModelInfo, Provider};
+use crate::recovery::{self, Recovered};
+use crate::retry::{self, RetryPolicy};
+use crate::streaming::{MatchScanner, SseDecoder};
+use crate::suggestion::{LlmMatch, LlmResponse, Severity, Suggestion};
+use crate::usage::Usage;
+use futures_util::stream::{self, StreamExt, TryStreamExt};
+use std::collections::HashSet;
 use std::sync::atomic::{AtomicU64, Ordering};
-use std::time::Instant;
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::{Duration, Instant};
+
+/// Pieces of one oversized text checked at once.
+const MAX_CONCURRENT_CHUNKS: usize = 4;
 
 static REQUEST_COUNTER: AtomicU64 = AtomicU64::new(0);
 
-const SYSTEM_PROMPT: &str = r#"You are a strict English writing assistant.
-Your job: suggest edits ONLY for:
-1. Grammatical errors.
-2. Typos.
-3. Phrases that are clearly awkward or non-native sounding.
-
-Rules:
-- Do NOT suggest stylistic variations if the original is correct.
-- Do NOT rewrite the text.
-- If a sentence is grammatically correct and clear, do NOT suggest anything.
-- If you have a comment (e.g., ambiguity) but no specific correction, leave "replacement" as null.
-
-Return ONLY valid JSON with this exact schema:
-{
-  "matches": [
-    {
-      "message": "explanation of the error",
-      "original": "exact text to replace",
-      "replacement": "corrected text or null",
-      "severity": "error|warning|suggestion"
+static RESPONSE_SCHEMA: LazyLock<serde_json::Value> = LazyLock::new(LlmResponse::json_schema);
+
+/// `provider/model` pairs that rejected a response schema this session; they
+/// get plain JSON mode and rely on the prompt's description of the format.
+static SCHEMA_UNSUPPORTED: LazyLock<Mutex<HashSet<String>>> =
+    LazyLock::new(|| Mutex::new(HashSet::new()));
+
+/// How a grammar check talks to the provider.
+#[derive(Debug, Clone)]
+pub struct CheckOptions {
+    /// Client carrying the proxy and certificate settings; `None` uses one
+    /// with default settings.
+    pub client: Option<reqwest::Client>,
+    /// Request a streamed response and report suggestions as they arrive.
+    pub stream: bool,
+    pub retry: RetryPolicy,
+    /// Where earlier results are looked up and new ones stored; `None` disables caching.
+    pub cache: Option<CheckCache>,
+    /// When the answer cannot be read even after repair, ask the model once
+    /// to resend it as valid JSON.
+    pub fix_invalid_json: bool,
+    /// Longest a single request may take, including a streamed answer.
+    pub timeout: Duration,
+    /// The model's context window in tokens, if listed; otherwise it is
+    /// guessed from the model name. Decides when text is split up.
+    pub context_window: Option<u64>,
+    /// The system prompt's template and placeholder values.
+    pub prompt: Prompt,
+    /// The language the text is in and the variety of English it should
+    /// follow; they decide which spelling changes are mere variants.
+    pub language: Lang,
+    pub dialect: Dialect,
+}
+
+impl Default for CheckOptions {
+    fn default() -> Self {
+        Self {
+            client: None,
+            stream: false,
+            retry: RetryPolicy::default(),
+            cache: None,
+            fix_invalid_json: false,
+            timeout: Duration::from_secs(60),
+            context_window: None,
+            prompt: Prompt::default(),
+            language: Lang::Eng,
+            dialect: Dialect::Any,
+        }
     }
-  ]
 }
 
-Severity levels:
-- "error": Grammar errors, typos, incorrect word usage
-- "warning": Awkward phrasing, non-native sounding expressions
-- "suggestion": Minor improvements, optional enhancements
+/// Things worth telling the user while a check is still running.
+#[derive(Debug, Clone)]
+pub enum Progress {
+    /// Suggestions parsed so far from a streamed response, not yet reported.
+    Partial(Vec<Suggestion>),
+    /// A transient failure; the request will be sent again after `delay`.
+    Retrying {
+        attempt: u32,
+        max_retries: u32,
+        delay: Duration,
+        reason: String,
+    },
+    /// Entries of a malformed response that could not be salvaged.
+    Dropped(usize),
+    /// Tokens billed for one request made by this check, and who billed them.
+    Usage {
+        provider: ApiProvider,
+        model: String,
+        usage: Usage,
+    },
+    /// The backend named `from` failed; the check moves on to `to`.
+    FallingBack {
+        from: String,
+        to: String,
+        reason: String,
+    },
+}
+
+pub type ProgressHandler<'a> = dyn Fn(Progress) + Send + Sync + 'a;
 
-IMPORTANT: The "original" field must contain the EXACT substring from the input (copy it precisely, including spacing).
-If there is nothing to change, return {"matches": []}."#;
+const FIX_JSON_PROMPT: &str = "Your previous reply was not valid JSON. Reply again with the same corrections as ONLY the JSON object described in the instructions: no code fences, no commentary.";
 
+/// Check `text`, splitting it into several concurrent requests when it would
+/// not fit in the model's context window. Suggestion offsets are relative to
+/// `text` either way.
 pub async fn check_grammar(
+    provider: &dyn Provider,
+    text: String,
+    model: String,
+    request_id: u64,
+    history: Vec<HistoryEntry>,
+    options: &CheckOptions,
+    on_progress: &ProgressHandler<'_>,
+) -> Result<(Vec<Suggestion>, u64), GrammyError> {
+    let limit = chunking::chunk_limit(
+        context_window(provider, &model, options),
+        &options.prompt.render(),
+        provider.max_answer_tokens(),
+    );
+    let chunks = chunking::split(&text, limit);
+    if chunks.len() == 1 {
+        return check_chunk(
+            provider,
+            text,
+            model,
+            request_id,
+            history,
+            options,
+            on_progress,
+        )
+        .await;
+    }
+
+    eprintln!(
+        "[DEBUG #{request_id}] Text of ~{} tokens exceeds the {}-token limit per request, checking it in {} chunks",
+        chunking::estimate_tokens(&text),
+        limit,
+        chunks.len()
+    );
+    let checks: Vec<_> = chunks
+        .into_iter()
+        .map(|chunk| {
+            check_offset_chunk(
+                provider,
+                chunk,
+                &model,
+                request_id,
+                &history,
+                options,
+                on_progress,
+            )
+        })
+        .collect();
+    let found: Vec<Vec<Suggestion>> = stream::iter(checks)
+        .buffered(MAX_CONCURRENT_CHUNKS)
+        .try_collect()
+        .await?;
+
+    Ok((found.into_iter().flatten().collect(), request_id))
+}
+
+/// Check the piece of a larger text starting at `offset`, with suggestion
+/// offsets relative to the larger text.
+async fn check_offset_chunk(
+    provider: &dyn Provider,
+    (offset, chunk): (usize, &str),
+    model: &str,
+    request_id: u64,
+    history: &[HistoryEntry],
+    options: &CheckOptions,
+    on_progress: &ProgressHandler<'_>,
+) -> Result<Vec<Suggestion>, GrammyError> {
+    let on_chunk_progress = |progress| match progress {
+        Progress::Partial(suggestions) => {
+            on_progress(Progress::Partial(shift_suggestions(suggestions, offset)))
+        }
+        progress => on_progress(progress),
+    };
+    let (suggestions, _) = check_chunk(
+        provider,
+        chunk.to_string(),
+        model.to_string(),
+        request_id,
+        history.to_vec(),
+        options,
+        &on_chunk_progress,
+    )
+    .await?;
+    Ok(shift_suggestions(suggestions, offset))
+}
+
+/// Check text that fits in one request, dropping the oldest history when
+/// the prompt would not fit otherwise.
+async fn check_chunk(
+    provider: &dyn Provider,
     text: String,
-    api_key: String,
     model: String,
-    provider: ApiProvider,
     request_id: u64,
     history: Vec<HistoryEntry>,
-) -> Result<(Vec<Suggestion>, u64), String> {
+    options: &CheckOptions,
+    on_progress: &ProgressHandler<'_>,
+) -> Result<(Vec<Suggestion>, u64), GrammyError> {
     let start = Instant::now();
+    let name = provider.kind().name();
     eprintln!(
         "[DEBUG #{request_id}] Starting grammar check, provider={}, model={}, text_len={}",
-        provider.name(),
+        name,
         model,
         text.len()
     );
 
-    if api_key.is_empty() {
+    if !provider.has_credentials() {
         eprintln!("[DEBUG #{request_id}] Error: API key not set");
-        return Err("API key not set. Click ⚙ to configure.".to_string());
+        return Err(GrammyError::MissingApiKey {
+            provider: provider.kind(),
+        });
     }
 
     if text.trim().is_empty() {
@@ -65,204 +228,571 @@ pub async fn check_grammar(
         return Ok((vec![], request_id));
     }
 
-    let client = reqwest::Client::builder()
-        .timeout(std::time::Duration::from_secs(60))
-        .build()
-        .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
-
-    // Build messages array: system prompt + history + current user message
-    let mut messages = vec![json!({ "role": "system", "content": SYSTEM_PROMPT })];
+    let system_prompt = options.prompt.render();
+    let cache_key = CacheKey {
+        provider: name,
+        base_url: provider.base_url(),
+        model: &model,
+        prompt: &system_prompt,
+        text: &text,
+    };
+    if let Some(matches) = options.cache.as_ref().and_then(|c| c.get(&cache_key)) {
+        eprintln!("[DEBUG #{request_id}] Cache hit, {} matches", matches.len());
+        return Ok((
+            convert_matches_to_suggestions(&text, matches, options),
+            request_id,
+        ));
+    }
 
-    // Add history entries (user/assistant pairs)
-    for entry in &history {
-        messages.push(json!({
-            "role": entry.role,
-            "content": entry.content
-        }));
+    let client = &options.client.clone().unwrap_or_default();
+    let user_message = format!("Text:\n{}", text);
+    let budget = chunking::prompt_budget(
+        context_window(provider, &model, options).unwrap_or(chunking::DEFAULT_CONTEXT_WINDOW),
+    );
+    let history_budget =
+        budget.saturating_sub(chunking::request_tokens(&system_prompt, &[], &user_message));
+    let history_len = history.len();
+    let history = chunking::trim_history(history, history_budget);
+    if history.len() < history_len {
+        eprintln!(
+            "[DEBUG #{request_id}] Dropped {} history entries to fit the context window",
+            history_len - history.len()
+        );
     }
+    let chat = ChatRequest {
+        model: &model,
+        system_prompt: &system_prompt,
+        history: &history,
+        user_message: &user_message,
+        stream: options.stream,
+        response_schema: None,
+    };
 
-    // Add current user message
-    messages.push(json!({
-        "role": "user",
-        "content": format!("Text:\n{}", text)
-    }));
+    let schema_key = format!("{}/{}", name, model);
+    let schema_supported = !SCHEMA_UNSUPPORTED
+        .lock()
+        .is_ok_and(|unsupported| unsupported.contains(&schema_key));
+    let structured = ChatRequest {
+        response_schema: schema_supported.then_some(&*RESPONSE_SCHEMA),
+        ..chat
+    };
 
-    let url = if provider == ApiProvider::Gemini {
-        format!(
-            "{}{}:generateContent?key={}",
-            provider.base_url(),
-            model,
-            api_key
-        )
-    } else {
-        provider.base_url().to_string()
+    eprintln!("[DEBUG #{request_id}] Sending request to {}", name);
+
+    let response = match send_with_retry(
+        provider,
+        client,
+        &structured,
+        options,
+        on_progress,
+        request_id,
+    )
+    .await
+    {
+        // Models without structured output support reject the schema outright
+        Err(GrammyError::Http { status, .. })
+            if structured.response_schema.is_some() && matches!(status.as_u16(), 400 | 422) =>
+        {
+            eprintln!(
+                "[DEBUG #{request_id}] {} rejected the response schema ({}), retrying in JSON mode",
+                schema_key, status
+            );
+            let response =
+                send_with_retry(provider, client, &chat, options, on_progress, request_id).await?;
+            if let Ok(mut unsupported) = SCHEMA_UNSUPPORTED.lock() {
+                unsupported.insert(schema_key);
+            }
+            response
+        }
+        result => result?,
     };
 
-    eprintln!("[DEBUG #{request_id}] Sending request to {}", url);
+    let (content, streamed) = if options.stream {
+        let (content, suggestions, usage) =
+            read_stream(provider, response, &text, options, on_progress, request_id).await?;
+        if let Some(usage) = usage {
+            on_progress(Progress::Usage {
+                provider: provider.kind(),
+                model: model.clone(),
+                usage,
+            });
+        }
+        (content, Some(suggestions))
+    } else {
+        let data: serde_json::Value = response.json().await.map_err(|e| {
+            eprintln!("[DEBUG #{request_id}] Failed to parse response: {}", e);
+            GrammyError::InvalidResponse(e.to_string())
+        })?;
+        if let Some(usage) = provider.extract_usage(&data) {
+            on_progress(Progress::Usage {
+                provider: provider.kind(),
+                model: model.clone(),
+                usage,
+            });
+        }
+        let content = provider
+            .extract_content(&data)
+            .unwrap_or_else(|| r#"{"matches":[]}"#.to_string());
+        (content, None)
+    };
 
-    let mut request = client.post(&url).header("Content-Type", "application/json");
+    eprintln!(
+        "[DEBUG #{request_id}] LLM response content: {}",
+        &content[..content.floor_char_boundary(200)]
+    );
 
-    if provider == ApiProvider::Gemini {
-        let body = json!({
-            "contents": [{
-                "parts": [{
-                    "text": format!("{}\n\nText:\n{}", SYSTEM_PROMPT, text)
-                }]
-            }],
-            "generationConfig": {
-                "responseMimeType": "application/json"
-            }
-        });
-        request = request.json(&body);
-    } else {
-        let body = json!({
-            "model": model,
-            "messages": messages,
-            "response_format": { "type": "json_object" }
-        });
-        request = request
-            .header("Authorization", format!("Bearer {}", api_key))
-            .json(&body);
-
-        // Add OpenRouter-specific headers
-        if provider == ApiProvider::OpenRouter {
-            request = request
-                .header("HTTP-Referer", "https://github.com/grammy-app")
-                .header("X-Title", "Grammy");
+    let recovered = match recovery::parse_response(&content) {
+        Ok(recovered) => recovered,
+        Err(e) if options.fix_invalid_json => {
+            eprintln!(
+                "[DEBUG #{request_id}] Invalid JSON from LLM: {}, asking for a fix",
+                e
+            );
+            request_json_fix(
+                provider,
+                client,
+                &chat,
+                &content,
+                options,
+                on_progress,
+                request_id,
+            )
+            .await?
         }
-    }
+        Err(e) => {
+            eprintln!("[DEBUG #{request_id}] Invalid JSON from LLM: {}", e);
+            return Err(GrammyError::InvalidLlmJson(e.to_string()));
+        }
+    };
 
-    let response = request.send().await.map_err(|e| {
+    if recovered.dropped > 0 {
         eprintln!(
-            "[DEBUG #{request_id}] Network error after {:?}: {}",
-            start.elapsed(),
-            e
+            "[DEBUG #{request_id}] Dropped {} malformed matches",
+            recovered.dropped
         );
-        format!("Network error: {}", e)
-    })?;
+        on_progress(Progress::Dropped(recovered.dropped));
+    } else if let Some(cache) = &options.cache {
+        // A partly salvaged answer is not worth keeping; the next check may do better
+        if let Err(e) = cache.put(&cache_key, &recovered.matches) {
+            eprintln!("[DEBUG #{request_id}] Failed to cache result: {}", e);
+        }
+    }
 
-    let status = response.status();
+    let parsed = convert_matches_to_suggestions(&text, recovered.matches, options);
+    let suggestions = match streamed {
+        Some(streamed) => merge_streamed(streamed, parsed),
+        None => parsed,
+    };
     eprintln!(
-        "[DEBUG #{request_id}] Response status: {} after {:?}",
-        status,
-        start.elapsed()
+        "[DEBUG #{request_id}] Completed in {:?}, found {} suggestions",
+        start.elapsed(),
+        suggestions.len()
     );
 
-    if !status.is_success() {
-        let error_body: serde_json::Value = response.json().await.unwrap_or_default();
-        let msg = if provider == ApiProvider::Gemini {
-            error_body["error"]["message"]
-                .as_str()
-                .unwrap_or("Unknown Gemini error")
-        } else {
-            error_body["error"]["message"]
-                .as_str()
-                .unwrap_or("Unknown error")
+    Ok((suggestions, request_id))
+}
+
+/// The suggestions reported while streaming, plus those only the full answer
+/// yielded, e.g. entries recovery repaired. Streamed suggestions were already
+/// handed out, so theirs are the IDs kept for a suggestion found by both.
+fn merge_streamed(mut streamed: Vec<Suggestion>, parsed: Vec<Suggestion>) -> Vec<Suggestion> {
+    for s in parsed {
+        let end = s.offset + s.length;
+        let overlaps = streamed
+            .iter()
+            .any(|o| s.offset < o.offset + o.length && o.offset < end);
+        if !overlaps {
+            streamed.push(s);
+        }
+    }
+    streamed.sort_by_key(|s| s.offset);
+    streamed
+}
+
+/// The model's context window, from its listing or the built-in table;
+/// `None` if neither knows it.
+fn context_window(provider: &dyn Provider, model: &str, options: &CheckOptions) -> Option<u64> {
+    options
+        .context_window
+        .or_else(|| chunking::context_window(&provider.kind(), model))
+}
+
+/// Make offsets relative to a text that starts `offset` bytes earlier.
+pub(crate) fn shift_suggestions(
+    mut suggestions: Vec<Suggestion>,
+    offset: usize,
+) -> Vec<Suggestion> {
+    for s in &mut suggestions {
+        s.offset += offset;
+    }
+    suggestions
+}
+
+/// A provider and model to run checks with.
+#[derive(Clone)]
+pub struct Backend {
+    pub provider: Arc<dyn Provider>,
+    pub model: String,
+    /// Context window from the model listing, if it has one.
+    pub context_window: Option<u64>,
+}
+
+impl Backend {
+    /// e.g. `OpenRouter · google/gemini-3-flash-preview`
+    pub fn label(&self) -> String {
+        format!("{} · {}", self.provider.kind().name(), self.model)
+    }
+}
+
+/// Check `text` with the first backend, moving down the list when one fails
+/// in a way the next might not: network errors, timeouts, 5xx, rate limits
+/// and unreadable answers. Later backends without credentials are skipped.
+/// Returns the index of the backend that answered, or the last failure.
+pub async fn check_with_fallbacks(
+    backends: &[Backend],
+    text: String,
+    request_id: u64,
+    history: Vec<HistoryEntry>,
+    options: &CheckOptions,
+    on_progress: &ProgressHandler<'_>,
+) -> Result<(Vec<Suggestion>, usize), GrammyError> {
+    let mut failure: Option<(&Backend, GrammyError)> = None;
+
+    for (index, backend) in backends.iter().enumerate() {
+        if index > 0 && !backend.provider.has_credentials() {
+            eprintln!(
+                "[DEBUG #{request_id}] Skipping fallback {}: no API key",
+                backend.label()
+            );
+            continue;
+        }
+        if let Some((from, error)) = failure.take() {
+            eprintln!(
+                "[DEBUG #{request_id}] {} failed ({}), falling back to {}",
+                from.label(),
+                error,
+                backend.label()
+            );
+            on_progress(Progress::FallingBack {
+                from: from.label(),
+                to: backend.label(),
+                reason: error.to_string(),
+            });
+        }
+
+        let options = CheckOptions {
+            context_window: backend.context_window,
+            ..options.clone()
         };
-        eprintln!("[DEBUG #{request_id}] API error: {} - {}", status, msg);
-        return Err(format!("{} error ({}): {}", provider.name(), status, msg));
+        let result = check_grammar(
+            backend.provider.as_ref(),
+            text.clone(),
+            backend.model.clone(),
+            request_id,
+            history.clone(),
+            &options,
+            on_progress,
+        )
+        .await;
+        match result {
+            Ok((suggestions, _)) => return Ok((suggestions, index)),
+            Err(e) if e.should_fall_back() => failure = Some((backend, e)),
+            Err(e) => return Err(e),
+        }
     }
 
-    let data: serde_json::Value = response.json().await.map_err(|e| {
-        eprintln!("[DEBUG #{request_id}] Failed to parse response: {}", e);
-        format!("Failed to parse response: {}", e)
-    })?;
+    Err(failure.map(|(_, e)| e).unwrap_or_else(|| {
+        GrammyError::Internal("no provider configured for checking".to_string())
+    }))
+}
 
-    let content = if provider == ApiProvider::Gemini {
-        data["candidates"][0]["content"]["parts"][0]["text"]
-            .as_str()
-            .unwrap_or(r#"{"matches":[]}"#)
-    } else {
-        data["choices"][0]["message"]["content"]
-            .as_str()
-            .unwrap_or(r#"{"matches":[]}"#)
+/// Follow up an unreadable answer by showing it to the model and asking for
+/// the same answer again as valid JSON.
+async fn request_json_fix(
+    provider: &dyn Provider,
+    client: &reqwest::Client,
+    chat: &ChatRequest<'_>,
+    invalid: &str,
+    options: &CheckOptions,
+    on_progress: &ProgressHandler<'_>,
+    request_id: u64,
+) -> Result<Recovered, GrammyError> {
+    let mut history = chat.history.to_vec();
+    history.push(HistoryEntry {
+        role: "user".to_string(),
+        content: chat.user_message.to_string(),
+    });
+    history.push(HistoryEntry {
+        role: "assistant".to_string(),
+        content: invalid.to_string(),
+    });
+    let follow_up = ChatRequest {
+        history: &history,
+        user_message: FIX_JSON_PROMPT,
+        stream: false,
+        ..*chat
     };
 
-    eprintln!(
-        "[DEBUG #{request_id}] LLM response content: {}",
-        &content[..content.len().min(200)]
-    );
+    let response = send_with_retry(
+        provider,
+        client,
+        &follow_up,
+        options,
+        on_progress,
+        request_id,
+    )
+    .await?;
+    let data: serde_json::Value = response
+        .json()
+        .await
+        .map_err(|e| GrammyError::InvalidResponse(e.to_string()))?;
+    if let Some(usage) = provider.extract_usage(&data) {
+        on_progress(Progress::Usage {
+            provider: provider.kind(),
+            model: chat.model.to_string(),
+            usage,
+        });
+    }
+    let content = provider
+        .extract_content(&data)
+        .unwrap_or_else(|| r#"{"matches":[]}"#.to_string());
 
-    let llm_response: LlmResponse = serde_json::from_str(content).map_err(|e| {
-        eprintln!("[DEBUG #{request_id}] Invalid JSON from LLM: {}", e);
-        format!("Invalid JSON from LLM: {}", e)
-    })?;
+    recovery::parse_response(&content).map_err(|e| {
+        eprintln!(
+            "[DEBUG #{request_id}] Fixed answer is still invalid JSON: {}",
+            e
+        );
+        GrammyError::InvalidLlmJson(e.to_string())
+    })
+}
 
-    let suggestions = convert_matches_to_suggestions(&text, llm_response.matches);
-    eprintln!(
-        "[DEBUG #{request_id}] Completed in {:?}, found {} suggestions",
-        start.elapsed(),
-        suggestions.len()
-    );
+/// Send the chat request, retrying rate limits, server errors and dropped
+/// connections according to `policy`. Only successful responses are returned.
+async fn send_with_retry(
+    provider: &dyn Provider,
+    client: &reqwest::Client,
+    chat: &ChatRequest<'_>,
+    options: &CheckOptions,
+    on_progress: &ProgressHandler<'_>,
+    request_id: u64,
+) -> Result<reqwest::Response, GrammyError> {
+    let policy = &options.retry;
+    let start = Instant::now();
+    let name = provider.kind().name();
+    let mut attempt = 0;
+
+    loop {
+        let (reason, requested_delay) = match provider
+            .chat_request(client, chat)
+            .timeout(options.timeout)
+            .send()
+            .await
+        {
+            Ok(response) => {
+                let status = response.status();
+                eprintln!(
+                    "[DEBUG #{request_id}] Response status: {} after {:?}",
+                    status,
+                    start.elapsed()
+                );
+                if status.is_success() {
+                    return Ok(response);
+                }
+                if !retry::is_retryable_status(status) || attempt >= policy.max_retries {
+                    return Err(error_from_response(provider, response, request_id).await);
+                }
+                let requested_delay = retry::retry_after(response.headers());
+                if requested_delay.is_some_and(|d| d > policy.max_delay) {
+                    // The server wants us gone for longer than we're willing to wait
+                    return Err(error_from_response(provider, response, request_id).await);
+                }
+                let reason = if status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+                    format!("{} rate limited", name)
+                } else {
+                    format!("{} error ({})", name, status)
+                };
+                (reason, requested_delay)
+            }
+            Err(e) => {
+                eprintln!(
+                    "[DEBUG #{request_id}] Network error after {:?}: {}",
+                    start.elapsed(),
+                    e
+                );
+                if !retry::is_retryable_error(&e) || attempt >= policy.max_retries {
+                    return Err(e.into());
+                }
+                ("Network error".to_string(), None)
+            }
+        };
 
-    Ok((suggestions, request_id))
+        attempt += 1;
+        let delay = requested_delay.unwrap_or_else(|| policy.backoff(attempt));
+        eprintln!(
+            "[DEBUG #{request_id}] {}; retry {}/{} in {:?}",
+            reason, attempt, policy.max_retries, delay
+        );
+        on_progress(Progress::Retrying {
+            attempt,
+            max_retries: policy.max_retries,
+            delay,
+            reason,
+        });
+        tokio::time::sleep(delay).await;
+    }
+}
+
+/// Turn an unsuccessful response into an error carrying the provider's explanation.
+async fn error_from_response(
+    provider: &dyn Provider,
+    response: reqwest::Response,
+    request_id: u64,
+) -> GrammyError {
+    let status = response.status();
+    let message = match response.json::<serde_json::Value>().await {
+        Ok(body) => provider
+            .error_message(&body)
+            .unwrap_or_else(|| format!("Unknown {} error", provider.kind().name())),
+        Err(_) => status
+            .canonical_reason()
+            .unwrap_or("Unknown error")
+            .to_string(),
+    };
+    eprintln!("[DEBUG #{request_id}] API error: {} - {}", status, message);
+
+    if status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+        GrammyError::RateLimited {
+            provider: provider.kind(),
+            message,
+        }
+    } else {
+        GrammyError::Http {
+            provider: provider.kind(),
+            status,
+            message,
+        }
+    }
+}
+
+/// Consume a streamed completion, reporting each suggestion as soon as its
+/// match is complete. Returns the full text and every suggestion reported.
+async fn read_stream(
+    provider: &dyn Provider,
+    mut response: reqwest::Response,
+    text: &str,
+    options: &CheckOptions,
+    on_progress: &ProgressHandler<'_>,
+    request_id: u64,
+) -> Result<(String, Vec<Suggestion>, Option<Usage>), GrammyError> {
+    let mut decoder = SseDecoder::default();
+    let mut scanner = MatchScanner::default();
+    let mut suggestions: Vec<Suggestion> = Vec::new();
+    let mut usage: Option<Usage> = None;
+
+    loop {
+        let chunk = response.chunk().await.map_err(|e| {
+            eprintln!("[DEBUG #{request_id}] Stream interrupted: {}", e);
+            GrammyError::from(e)
+        })?;
+        let events = match &chunk {
+            Some(bytes) => decoder.push(bytes),
+            None => decoder.finish().into_iter().collect(),
+        };
+
+        for event in &events {
+            if event == "[DONE]" {
+                continue;
+            }
+            let Ok(value) = serde_json::from_str::<serde_json::Value>(event) else {
+                continue;
+            };
+            if value.get("error").is_some() {
+                let msg = provider
+                    .error_message(&value)
+                    .unwrap_or_else(|| value.to_string());
+                eprintln!("[DEBUG #{request_id}] Error in stream: {}", msg);
+                return Err(GrammyError::Stream {
+                    provider: provider.kind(),
+                    message: msg,
+                });
+            }
+            if let Some(reported) = provider.extract_usage(&value) {
+                usage = Some(usage.map_or(reported, |u| u.merge(reported)));
+            }
+            let Some(delta) = provider.extract_stream_delta(&value) else {
+                continue;
+            };
+
+            let mut fresh = Vec::new();
+            for m in scanner.push(&delta) {
+                let Some(s) = locate_match(text, m, options) else {
+                    continue;
+                };
+                let end = s.offset + s.length;
+                let overlaps = suggestions
+                    .iter()
+                    .any(|o| s.offset < o.offset + o.length && o.offset < end);
+                if !overlaps {
+                    suggestions.push(s.clone());
+                    fresh.push(s);
+                }
+            }
+            if !fresh.is_empty() {
+                on_progress(Progress::Partial(fresh));
+            }
+        }
+
+        if chunk.is_none() {
+            break;
+        }
+    }
+
+    suggestions.sort_by_key(|s| s.offset);
+    Ok((scanner.text().to_string(), suggestions, usage))
 }
 
 pub fn next_request_id() -> u64 {
     REQUEST_COUNTER.fetch_add(1, Ordering::SeqCst)
 }
 
+/// Check the URL, credentials and model, going through the proxy and
+/// certificates `client` was built with.
 pub async fn test_connection(
-    api_key: String,
-    provider: ApiProvider,
+    provider: &dyn Provider,
+    client: &reqwest::Client,
     model: String,
+    timeout: Duration,
     request_id: u64,
-) -> Result<u64, String> {
+) -> Result<u64, GrammyError> {
     let start = Instant::now();
+    let name = provider.kind().name();
     eprintln!(
         "[DEBUG #{request_id}] Starting connection test, provider={}, model={}",
-        provider.name(),
-        model
+        name, model
     );
 
-    if api_key.is_empty() {
+    if !provider.has_credentials() {
         eprintln!("[DEBUG #{request_id}] Error: API key not set");
-        return Err("API key not set. Click ⚙ to configure.".to_string());
-    }
-
-    let client = reqwest::Client::builder()
-        .timeout(std::time::Duration::from_secs(20))
-        .build()
-        .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
-
-    let (url, is_post) = match provider {
-        ApiProvider::OpenAI => ("https://api.openai.com/v1/models".to_string(), false),
-        ApiProvider::OpenRouter => ("https://openrouter.ai/api/v1/key".to_string(), false),
-        ApiProvider::Gemini => (
-            format!(
-                "https://generativelanguage.googleapis.com/v1beta/models?key={}",
-                api_key
-            ),
-            false,
-        ),
-    };
-
-    eprintln!("[DEBUG #{request_id}] Sending test request to {}", url);
-
-    let mut request = if is_post {
-        client.post(&url)
-    } else {
-        client.get(&url)
-    };
-
-    if provider != ApiProvider::Gemini {
-        request = request.header("Authorization", format!("Bearer {}", api_key));
-    }
-
-    if provider == ApiProvider::OpenRouter {
-        request = request
-            .header("HTTP-Referer", "https://github.com/grammy-app")
-            .header("X-Title", "Grammy");
+        return Err(GrammyError::MissingApiKey {
+            provider: provider.kind(),
+        });
     }
 
-    let response = request.send().await.map_err(|e| {
-        eprintln!(
-            "[DEBUG #{request_id}] Network error after {:?}: {}",
-            start.elapsed(),
-            e
-        );
-        format!("Network error: {}", e)
-    })?;
+    eprintln!("[DEBUG #{request_id}] Sending test request to {}", name);
+
+    let response = provider
+        .health_check_request(client)
+        .timeout(timeout)
+        .send()
+        .await
+        .map_err(|e| {
+            eprintln!(
+                "[DEBUG #{request_id}] Network error after {:?}: {}",
+                start.elapsed(),
+                e
+            );
+            GrammyError::from(e)
+        })?;
 
     let status = response.status();
     eprintln!(
@@ -272,33 +802,16 @@ pub async fn test_connection(
     );
 
     if !status.is_success() {
-        let msg = match response.json::<serde_json::Value>().await {
-            Ok(v) => v
-                .get("error")
-                .and_then(|e| e.get("message"))
-                .and_then(|m| m.as_str())
-                .map(|s| s.to_string())
-                .or_else(|| {
-                    v.get("message")
-                        .and_then(|m| m.as_str())
-                        .map(|s| s.to_string())
-                })
-                .unwrap_or_else(|| v.to_string()),
-            Err(_) => "Unauthorized".to_string(),
-        };
-        eprintln!("[DEBUG #{request_id}] Test API error: {} - {}", status, msg);
-        return Err(format!("{} error ({}): {}", provider.name(), status, msg));
+        return Err(error_from_response(provider, response, request_id).await);
     }
 
-    // If we're here, connection is OK. Now validate model if provided and not Gemini (which lists models already)
-    // Actually, let's just check if the model is in the list of models for the provider.
-    let models = fetch_models(provider.clone(), api_key).await?;
-    if !model.is_empty() && !models.iter().any(|m| m == &model) {
-        return Err(format!(
-            "Model '{}' not found for {}",
+    // Connection is OK; make sure the selected model actually exists.
+    let models = fetch_models(provider, client, timeout, request_id).await?;
+    if !model.is_empty() && !models.iter().any(|m| m.id == model) {
+        return Err(GrammyError::ModelNotFound {
+            provider: provider.kind(),
             model,
-            provider.name()
-        ));
+        });
     }
 
     eprintln!(
@@ -308,98 +821,45 @@ pub async fn test_connection(
     Ok(request_id)
 }
 
-pub async fn fetch_models(provider: ApiProvider, api_key: String) -> Result<Vec<String>, String> {
-    if api_key.is_empty() {
+pub async fn fetch_models(
+    provider: &dyn Provider,
+    client: &reqwest::Client,
+    timeout: Duration,
+    request_id: u64,
+) -> Result<Vec<ModelInfo>, GrammyError> {
+    if !provider.has_credentials() {
         return Ok(vec![]);
     }
 
-    let client = reqwest::Client::new();
-    let url = match provider {
-        ApiProvider::OpenAI => "https://api.openai.com/v1/models".to_string(),
-        ApiProvider::OpenRouter => "https://openrouter.ai/api/v1/models".to_string(),
-        ApiProvider::Gemini => format!(
-            "https://generativelanguage.googleapis.com/v1beta/models?key={}",
-            api_key
-        ),
-    };
-
-    let mut request = client.get(&url);
-    if provider != ApiProvider::Gemini {
-        request = request.header("Authorization", format!("Bearer {}", api_key));
-    }
-
-    let response = request.send().await.map_err(|e| e.to_string())?;
+    let response = provider
+        .models_request(client)
+        .timeout(timeout)
+        .send()
+        .await?;
 
     if !response.status().is_success() {
-        return Err(format!("Failed to fetch models: {}", response.status()));
+        return Err(error_from_response(provider, response, request_id).await);
     }
 
-    let data: serde_json::Value = response.json().await.map_err(|e| e.to_string())?;
-    let mut models = Vec::new();
-
-    match provider {
-        ApiProvider::OpenAI | ApiProvider::OpenRouter => {
-            if let Some(data_array) = data["data"].as_array() {
-                for m in data_array {
-                    if let Some(id) = m["id"].as_str() {
-                        models.push(id.to_string());
-                    }
-                }
-            }
-        }
-        ApiProvider::Gemini => {
-            if let Some(models_array) = data["models"].as_array() {
-                for m in models_array {
-                    if let Some(name) = m["name"].as_str() {
-                        // Gemini returns "models/gemini-pro", we want just "gemini-pro"
-                        let name = name.strip_prefix("models/").unwrap_or(name);
-                        models.push(name.to_string());
-                    }
-                }
-            }
-        }
-    }
+    let data: serde_json::Value = response
+        .json()
+        .await
+        .map_err(|e| GrammyError::InvalidResponse(e.to_string()))?;
+    let mut models = provider.parse_models(&data);
 
-    models.sort();
+    models.sort_by(|a, b| a.id.cmp(&b.id));
     Ok(models)
 }
 
-fn convert_matches_to_suggestions(text: &str, matches: Vec<LlmMatch>) -> Vec<Suggestion> {
-    let mut suggestions = Vec::new();
-
-    for m in matches {
-        if m.original.is_empty() {
-            continue;
-        }
-
-        // If we have a replacement, ensure it's different from original and not empty
-        if let Some(ref repl) = m.replacement {
-            if repl.is_empty() || repl == &m.original {
-                continue;
-            }
-        }
-
-        let offset = if let Some(pos) = text.find(&m.original) {
-            pos
-        } else {
-            // Try case-insensitive search
-            let lower_text = text.to_lowercase();
-            let lower_original = m.original.to_lowercase();
-            if let Some(pos) = lower_text.find(&lower_original) {
-                pos
-            } else {
-                continue;
-            }
-        };
-
-        suggestions.push(Suggestion::new(
-            m.message,
-            offset,
-            m.original,
-            m.replacement,
-            m.severity,
-        ));
-    }
+fn convert_matches_to_suggestions(
+    text: &str,
+    matches: Vec<LlmMatch>,
+    options: &CheckOptions,
+) -> Vec<Suggestion> {
+    let mut suggestions: Vec<Suggestion> = matches
+        .into_iter()
+        .filter_map(|m| locate_match(text, m, options))
+        .collect();
 
     suggestions.sort_by_key(|s| s.offset);
 
@@ -418,10 +878,71 @@ fn convert_matches_to_suggestions(text: &str, matches: Vec<LlmMatch>) -> Vec<Sug
     filtered
 }
 
+/// Turn a match into a suggestion positioned in `text`, dropping matches that
+/// are no-ops, flag a dictionary word, or whose `original` cannot be found.
+fn locate_match(text: &str, m: LlmMatch, options: &CheckOptions) -> Option<Suggestion> {
+    if m.original.is_empty() || dictionary::contains(&options.prompt.dictionary, &m.original) {
+        return None;
+    }
+
+    // If we have a replacement, ensure it's different from original and not empty
+    if let Some(ref repl) = m.replacement {
+        if repl.is_empty() || repl == &m.original {
+            return None;
+        }
+    }
+
+    // The suggestion covers the text as written, whatever case the model used
+    let (offset, original) = match text.find(&m.original) {
+        Some(pos) => (pos, m.original),
+        None => {
+            let (pos, len) = find_ignoring_case(text, &m.original)?;
+            (pos, text[pos..pos + len].to_string())
+        }
+    };
+
+    // Models often call a regional spelling a typo. Only English comes in
+    // the varieties offered.
+    let dialect = match options.language {
+        Lang::Eng => options.dialect,
+        _ => Dialect::Any,
+    };
+    let severity = match &m.replacement {
+        Some(repl) if dialect::moves_away_from(dialect, &original, repl) => Severity::Variant,
+        _ => m.severity,
+    };
+
+    Some(Suggestion::new(
+        m.message,
+        offset,
+        original,
+        m.replacement,
+        severity,
+    ))
+}
+
+/// Byte offset and length of the first match of `needle` in `text`, ignoring
+/// case. Compares character by character in `text` itself, since lowercasing
+/// can change byte lengths (`İ` becomes `i̇`) and with them the offsets.
+fn find_ignoring_case(text: &str, needle: &str) -> Option<(usize, usize)> {
+    let same = |a: char, b: char| a == b || a.to_lowercase().eq(b.to_lowercase());
+    text.char_indices().find_map(|(start, _)| {
+        let mut rest = text[start..].char_indices();
+        let mut len = 0;
+        for wanted in needle.chars() {
+            let (i, c) = rest.next()?;
+            if !same(c, wanted) {
+                return None;
+            }
+            len = i + c.len_utf8();
+        }
+        Some((start, len))
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::suggestion::Severity;
 
     #[test]
     fn test_normal_suggestion() {
@@ -433,7 +954,7 @@ mod tests {
             severity: Severity::Error,
         }];
 
-        let suggestions = convert_matches_to_suggestions(text, matches);
+        let suggestions = convert_matches_to_suggestions(text, matches, &CheckOptions::default());
         assert_eq!(suggestions.len(), 1);
         assert_eq!(suggestions[0].original, "has");
         assert_eq!(suggestions[0].replacement, Some("have".to_string()));
@@ -449,12 +970,112 @@ mod tests {
             severity: Severity::Warning,
         }];
 
-        let suggestions = convert_matches_to_suggestions(text, matches);
+        let suggestions = convert_matches_to_suggestions(text, matches, &CheckOptions::default());
         assert_eq!(suggestions.len(), 1);
         assert_eq!(suggestions[0].original, "has");
         assert!(suggestions[0].replacement.is_none());
     }
 
+    #[test]
+    fn test_spelling_variants_are_not_errors() {
+        let text = "The colour is nice.";
+        let matches = vec![LlmMatch {
+            message: "Spelling".to_string(),
+            original: "colour".to_string(),
+            replacement: Some("color".to_string()),
+            severity: Severity::Error,
+        }];
+
+        let british = CheckOptions {
+            dialect: Dialect::British,
+            ..CheckOptions::default()
+        };
+        let suggestions = convert_matches_to_suggestions(text, matches.clone(), &british);
+        assert_eq!(suggestions[0].severity, Severity::Variant);
+
+        // Without a preference, or in another language, the model's label stands
+        let suggestions =
+            convert_matches_to_suggestions(text, matches.clone(), &CheckOptions::default());
+        assert_eq!(suggestions[0].severity, Severity::Error);
+        let french = CheckOptions {
+            language: Lang::Fra,
+            ..british
+        };
+        let suggestions = convert_matches_to_suggestions(text, matches, &french);
+        assert_eq!(suggestions[0].severity, Severity::Error);
+    }
+
+    #[test]
+    fn test_dictionary_words_are_not_flagged() {
+        let text = "Grammy checks iced apps.";
+        let matches = vec![
+            LlmMatch {
+                message: "Spelling".to_string(),
+                original: "Grammy".to_string(),
+                replacement: Some("Grammar".to_string()),
+                severity: Severity::Error,
+            },
+            LlmMatch {
+                message: "Capitalisation".to_string(),
+                original: "iced".to_string(),
+                replacement: Some("Iced".to_string()),
+                severity: Severity::Warning,
+            },
+        ];
+
+        let options = CheckOptions {
+            prompt: Prompt {
+                dictionary: vec!["Grammy".to_string()],
+                ..Prompt::default()
+            },
+            ..CheckOptions::default()
+        };
+        let suggestions = convert_matches_to_suggestions(text, matches, &options);
+        assert_eq!(suggestions.len(), 1);
+        assert_eq!(suggestions[0].original, "iced");
+    }
+
+    #[test]
+    fn test_streamed_suggestions_merge_with_the_full_answer() {
+        let text = "I has a cat an a dog.";
+        let m = |original: &str, replacement: &str| LlmMatch {
+            message: "grammar error".to_string(),
+            original: original.to_string(),
+            replacement: Some(replacement.to_string()),
+            severity: Severity::Error,
+        };
+        let streamed =
+            convert_matches_to_suggestions(text, vec![m("has", "have")], &CheckOptions::default());
+        let parsed = convert_matches_to_suggestions(
+            text,
+            vec![m("has", "have"), m("an", "and")],
+            &CheckOptions::default(),
+        );
+
+        let merged = merge_streamed(streamed.clone(), parsed);
+        let originals: Vec<&str> = merged.iter().map(|s| s.original.as_str()).collect();
+        assert_eq!(originals, vec!["has", "an"]);
+        assert_eq!(merged[0].id, streamed[0].id);
+    }
+
+    #[test]
+    fn test_case_insensitive_match_in_non_ascii_text() {
+        // Lowercasing "İ" adds a byte, which used to shift the offset
+        let text = "İstanbul'da Bir kedi var.";
+        let matches = vec![LlmMatch {
+            message: "Capitalisation".to_string(),
+            original: "bir".to_string(),
+            replacement: Some("bir tane".to_string()),
+            severity: Severity::Warning,
+        }];
+
+        let suggestions = convert_matches_to_suggestions(text, matches, &CheckOptions::default());
+        assert_eq!(suggestions.len(), 1);
+        let s = &suggestions[0];
+        assert_eq!(&text[s.offset..s.offset + s.length], "Bir");
+        assert_eq!(s.original, "Bir");
+    }
+
     #[test]
     fn test_empty_replacement_ignored() {
         let text = "I has a cat.";
@@ -465,7 +1086,7 @@ mod tests {
             severity: Severity::Error,
         }];
 
-        let suggestions = convert_matches_to_suggestions(text, matches);
+        let suggestions = convert_matches_to_suggestions(text, matches, &CheckOptions::default());
         assert_eq!(suggestions.len(), 0);
     }
 
@@ -489,7 +1110,7 @@ mod tests {
             },
         ];
 
-        let suggestions = convert_matches_to_suggestions(text, matches);
+        let suggestions = convert_matches_to_suggestions(text, matches, &CheckOptions::default());
         // Should keep "I has" (starts at 0) and drop "has" (starts at 2, which is < 0+5)
         assert_eq!(suggestions.len(), 1);
         assert_eq!(suggestions[0].original, "I has");
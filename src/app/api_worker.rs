@@ -1,28 +1,59 @@
+use std::collections::HashMap;
 use std::sync::mpsc::{Receiver, Sender};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
 
-use crate::api;
+use reqwest::Client;
+use tokio::sync::Semaphore;
+use tokio::task::{AbortHandle, JoinHandle, JoinSet};
+
+use crate::api::{self, Backend, CheckOptions, Progress};
 use crate::config::ApiProvider;
+use crate::error::GrammyError;
+use crate::network::NetworkSettings;
+use crate::provider::{Endpoint, ModelInfo};
 use crate::suggestion::Suggestion;
+use crate::usage::Usage;
 
 use super::history::HistoryEntry;
+use super::paragraphs::Paragraph;
+
+/// Paragraphs checked at once; more would mostly earn rate limits.
+const MAX_CONCURRENT_PARAGRAPHS: usize = 4;
 
 #[derive(Debug)]
 pub(super) enum ApiJob {
+    /// Check each paragraph separately; the responses name paragraphs by
+    /// their index here, and suggestion offsets are relative to them.
     Grammar {
-        text: String,
-        api_key: String,
-        model: String,
-        provider: ApiProvider,
+        paragraphs: Vec<Paragraph>,
+        /// Provider, model and listed context window of each backend, tried
+        /// in order; see `api::check_with_fallbacks`.
+        backends: Vec<(Endpoint, String, Option<u64>)>,
         history: Vec<HistoryEntry>,
+        network: NetworkSettings,
+        /// Boxed, being much larger than the other jobs.
+        options: Box<CheckOptions>,
     },
     TestConnection {
-        api_key: String,
-        provider: ApiProvider,
+        endpoint: Endpoint,
         model: String,
+        network: NetworkSettings,
+        timeout: Duration,
     },
     FetchModels {
-        api_key: String,
-        provider: ApiProvider,
+        endpoint: Endpoint,
+        network: NetworkSettings,
+        timeout: Duration,
+    },
+    /// Abort every running grammar check.
+    CancelGrammar,
+    /// Abort some paragraphs of a running check, the rest carrying on.
+    CancelParagraphs {
+        /// Request id of the check.
+        check: u64,
+        /// Indices of the paragraphs in its job.
+        paragraphs: Vec<usize>,
     },
 }
 
@@ -34,99 +65,657 @@ pub(super) struct ApiRequest {
 
 #[derive(Debug, Clone)]
 pub(super) enum ApiResponse {
-    GrammarSuccess {
+    /// Suggestions parsed so far from a paragraph's streamed response, not
+    /// yet reported.
+    GrammarPartial {
+        paragraph: usize,
+        suggestions: Vec<Suggestion>,
+        request_id: u64,
+    },
+    /// Every suggestion for a paragraph, sent as soon as it is checked.
+    GrammarParagraph {
+        paragraph: usize,
         suggestions: Vec<Suggestion>,
         request_id: u64,
     },
+    GrammarRetrying {
+        attempt: u32,
+        max_retries: u32,
+        delay: Duration,
+        reason: String,
+        request_id: u64,
+    },
+    /// Entries of a malformed response that could not be salvaged.
+    GrammarDropped {
+        count: usize,
+        request_id: u64,
+    },
+    /// A backend failed and the check moved on to the next one.
+    GrammarFallback {
+        from: String,
+        to: String,
+        reason: String,
+        request_id: u64,
+    },
+    /// Tokens billed for one request, reported even for superseded checks.
+    GrammarUsage {
+        provider: ApiProvider,
+        model: String,
+        usage: Usage,
+        request_id: u64,
+    },
+    /// Every paragraph not cancelled has been reported.
+    GrammarSuccess {
+        /// Labels of the backends that answered, fallbacks marked as such.
+        sources: Vec<String>,
+        request_id: u64,
+    },
     GrammarError {
-        message: String,
+        error: GrammyError,
         request_id: u64,
     },
     TestSuccess {
         request_id: u64,
     },
     TestError {
-        message: String,
+        error: GrammyError,
         request_id: u64,
     },
     ModelsSuccess {
-        models: Vec<String>,
+        models: Vec<ModelInfo>,
         provider: ApiProvider,
+        request_id: u64,
     },
     ModelsError {
-        message: String,
+        error: GrammyError,
+        provider: ApiProvider,
+        request_id: u64,
     },
 }
 
+/// The HTTP client every job goes through, so connections, HTTP/2 sessions
+/// and TLS sessions carry over from one check to the next. Rebuilt only when
+/// a job comes with different network settings.
+#[derive(Default)]
+struct SharedClient {
+    current: Option<(NetworkSettings, Client)>,
+}
+
+impl SharedClient {
+    fn get(&mut self, network: &NetworkSettings) -> Result<Client, GrammyError> {
+        if let Some((settings, client)) = &self.current {
+            if settings == network {
+                return Ok(client.clone());
+            }
+        }
+        eprintln!("[DEBUG] Building HTTP client for new network settings");
+        let client = network.client()?;
+        self.current = Some((network.clone(), client.clone()));
+        Ok(client)
+    }
+}
+
 pub(super) fn spawn_api_worker(request_rx: Receiver<ApiRequest>, response_tx: Sender<ApiResponse>) {
     std::thread::spawn(move || {
         eprintln!("[DEBUG] API thread started");
         let rt = tokio::runtime::Runtime::new().expect("Failed to create runtime");
 
+        let mut shared_client = SharedClient::default();
+        // Checks run side by side until they finish or are cancelled, since a
+        // new one only covers the paragraphs edited since.
+        let mut grammar_tasks: HashMap<u64, GrammarTask> = HashMap::new();
+        let limit = Arc::new(Semaphore::new(MAX_CONCURRENT_PARAGRAPHS));
+
         while let Ok(req) = request_rx.recv() {
             eprintln!("[DEBUG] API thread received request #{}", req.request_id);
             let tx = response_tx.clone();
-            let request_id = req.request_id;
+            grammar_tasks.retain(|_, task| !task.handle.is_finished());
 
-            rt.block_on(async {
-                match req.job {
-                    ApiJob::Grammar {
-                        text,
-                        api_key,
-                        model,
-                        provider,
-                        history,
-                    } => match api::check_grammar(
-                        text, api_key, model, provider, request_id, history,
-                    )
-                    .await
-                    {
-                        Ok((suggestions, req_id)) => {
-                            let _ = tx.send(ApiResponse::GrammarSuccess {
-                                suggestions,
-                                request_id: req_id,
-                            });
-                        }
-                        Err(e) => {
-                            let _ = tx.send(ApiResponse::GrammarError {
-                                message: e,
-                                request_id,
-                            });
-                        }
+            let network = match &req.job {
+                ApiJob::Grammar { network, .. }
+                | ApiJob::TestConnection { network, .. }
+                | ApiJob::FetchModels { network, .. } => network,
+                ApiJob::CancelGrammar => {
+                    for (_, task) in grammar_tasks.drain() {
+                        task.handle.abort();
+                    }
+                    continue;
+                }
+                ApiJob::CancelParagraphs { check, paragraphs } => {
+                    if let Some(task) = grammar_tasks.get(check) {
+                        task.abort_paragraphs(paragraphs);
+                    }
+                    continue;
+                }
+            };
+            let client = shared_client.get(network);
+
+            match req.job {
+                ApiJob::Grammar {
+                    paragraphs,
+                    backends,
+                    history,
+                    options,
+                    ..
+                } => {
+                    let aborts = ParagraphAborts::default();
+                    let handle = rt.spawn(run_grammar(
+                        GrammarJob {
+                            paragraphs,
+                            backends,
+                            history,
+                            options,
+                        },
+                        client,
+                        req.request_id,
+                        tx,
+                        limit.clone(),
+                        aborts.clone(),
+                    ));
+                    grammar_tasks.insert(req.request_id, GrammarTask { handle, aborts });
+                }
+                // Settings operations run alongside any grammar check
+                job => {
+                    rt.spawn(run_job(job, client, req.request_id, tx));
+                }
+            }
+        }
+
+        eprintln!("[DEBUG] API thread exiting");
+    });
+}
+
+/// A running grammar check.
+struct GrammarTask {
+    handle: JoinHandle<()>,
+    aborts: ParagraphAborts,
+}
+
+impl GrammarTask {
+    fn abort_paragraphs(&self, paragraphs: &[usize]) {
+        let mut aborts = self.aborts.lock().unwrap_or_else(PoisonError::into_inner);
+        for &index in paragraphs {
+            if let Some(abort) = aborts.started.get(&index) {
+                abort.abort();
+            }
+            aborts.cancelled.push(index);
+        }
+    }
+}
+
+/// Abort handles of a check's paragraphs, by their index in the job, and
+/// the ones cancelled, which may not have been started yet.
+#[derive(Default)]
+struct Aborts {
+    started: HashMap<usize, AbortHandle>,
+    cancelled: Vec<usize>,
+}
+
+type ParagraphAborts = Arc<Mutex<Aborts>>;
+
+/// The parts of an `ApiJob::Grammar` a check needs once its client is set up.
+struct GrammarJob {
+    paragraphs: Vec<Paragraph>,
+    backends: Vec<(Endpoint, String, Option<u64>)>,
+    history: Vec<HistoryEntry>,
+    options: Box<CheckOptions>,
+}
+
+/// Check each paragraph of `job`, at most `limit` at a time across all
+/// checks, reporting each one's suggestions as soon as it is done.
+async fn run_grammar(
+    job: GrammarJob,
+    client: Result<Client, GrammyError>,
+    request_id: u64,
+    tx: Sender<ApiResponse>,
+    limit: Arc<Semaphore>,
+    aborts: ParagraphAborts,
+) {
+    let client = match client {
+        Ok(client) => client,
+        Err(error) => {
+            let _ = tx.send(ApiResponse::GrammarError { error, request_id });
+            return;
+        }
+    };
+    // One client for all paragraphs, so they share its connections
+    let options = CheckOptions {
+        client: Some(client),
+        ..*job.options
+    };
+    let backends: Arc<[Backend]> = job
+        .backends
+        .into_iter()
+        .map(|(endpoint, model, context_window)| Backend {
+            provider: Arc::from(endpoint.build()),
+            model,
+            context_window,
+        })
+        .collect();
+    // Dropping the set aborts every paragraph when this job is aborted
+    let mut checks = JoinSet::new();
+
+    for (index, paragraph) in job.paragraphs.into_iter().enumerate() {
+        let backends = backends.clone();
+        let limit = limit.clone();
+        let history = job.history.clone();
+        let options = options.clone();
+        let tx = tx.clone();
+
+        // Held until the abort handle is in, so no cancel goes unseen
+        let mut paragraph_aborts = aborts.lock().unwrap_or_else(PoisonError::into_inner);
+        if paragraph_aborts.cancelled.contains(&index) {
+            continue;
+        }
+        let abort = checks.spawn(async move {
+            let _permit = limit.acquire_owned().await;
+            let on_progress = move |progress| {
+                let response = match progress {
+                    Progress::Partial(suggestions) => ApiResponse::GrammarPartial {
+                        paragraph: index,
+                        suggestions,
+                        request_id,
                     },
-                    ApiJob::TestConnection {
-                        api_key,
+                    Progress::Retrying {
+                        attempt,
+                        max_retries,
+                        delay,
+                        reason,
+                    } => ApiResponse::GrammarRetrying {
+                        attempt,
+                        max_retries,
+                        delay,
+                        reason,
+                        request_id,
+                    },
+                    Progress::Dropped(count) => ApiResponse::GrammarDropped { count, request_id },
+                    Progress::Usage {
+                        provider,
+                        model,
+                        usage,
+                    } => ApiResponse::GrammarUsage {
                         provider,
                         model,
-                    } => match api::test_connection(api_key, provider, model, request_id).await {
-                        Ok(req_id) => {
-                            let _ = tx.send(ApiResponse::TestSuccess { request_id: req_id });
-                        }
-                        Err(e) => {
-                            let _ = tx.send(ApiResponse::TestError {
-                                message: e,
-                                request_id,
-                            });
-                        }
+                        usage,
+                        request_id,
                     },
-                    ApiJob::FetchModels { api_key, provider } => {
-                        let provider_clone = provider.clone();
-                        match api::fetch_models(provider, api_key).await {
-                            Ok(models) => {
-                                let _ = tx.send(ApiResponse::ModelsSuccess {
-                                    models,
-                                    provider: provider_clone,
-                                });
-                            }
-                            Err(e) => {
-                                let _ = tx.send(ApiResponse::ModelsError { message: e });
-                            }
-                        }
-                    }
+                    Progress::FallingBack { from, to, reason } => ApiResponse::GrammarFallback {
+                        from,
+                        to,
+                        reason,
+                        request_id,
+                    },
+                };
+                let _ = tx.send(response);
+            };
+            let result = api::check_with_fallbacks(
+                &backends,
+                paragraph.text,
+                request_id,
+                history,
+                &options,
+                &on_progress,
+            )
+            .await;
+            // Written out right away: an edit may abort the rest of the job
+            if let Some(cache) = &options.cache {
+                if let Err(e) = cache.flush() {
+                    eprintln!("[DEBUG #{request_id}] Failed to write the cache: {}", e);
                 }
-            });
+            }
+            result.map(|(suggestions, used)| (index, suggestions, used))
+        });
+        paragraph_aborts.started.insert(index, abort);
+    }
+
+    let mut used = Vec::new();
+    let mut error = None;
+    while let Some(joined) = checks.join_next().await {
+        match joined {
+            Ok(Ok((paragraph, suggestions, backend))) => {
+                let _ = tx.send(ApiResponse::GrammarParagraph {
+                    paragraph,
+                    suggestions,
+                    request_id,
+                });
+                used.push(backend);
+            }
+            Ok(Err(e)) => {
+                error.get_or_insert(e);
+            }
+            // Edited while it was being checked
+            Err(e) if e.is_cancelled() => {}
+            Err(e) => {
+                error.get_or_insert(GrammyError::Internal(format!(
+                    "paragraph check failed ({})",
+                    e
+                )));
+            }
         }
+    }
 
-        eprintln!("[DEBUG] API thread exiting");
-    });
+    let response = match error {
+        None => {
+            used.sort();
+            used.dedup();
+            let sources = used
+                .into_iter()
+                .map(|i| match i {
+                    0 => backends[i].label(),
+                    _ => format!("{} (fallback)", backends[i].label()),
+                })
+                .collect();
+            ApiResponse::GrammarSuccess {
+                sources,
+                request_id,
+            }
+        }
+        // What the other paragraphs found has been reported already
+        Some(error) => ApiResponse::GrammarError { error, request_id },
+    };
+    let _ = tx.send(response);
+}
+
+async fn run_job(
+    job: ApiJob,
+    client: Result<Client, GrammyError>,
+    request_id: u64,
+    tx: Sender<ApiResponse>,
+) {
+    match job {
+        ApiJob::TestConnection {
+            endpoint,
+            model,
+            timeout,
+            ..
+        } => {
+            let result = match client {
+                Ok(client) => {
+                    let provider = endpoint.build();
+                    api::test_connection(provider.as_ref(), &client, model, timeout, request_id)
+                        .await
+                }
+                Err(e) => Err(e),
+            };
+            match result {
+                Ok(req_id) => {
+                    let _ = tx.send(ApiResponse::TestSuccess { request_id: req_id });
+                }
+                Err(e) => {
+                    let _ = tx.send(ApiResponse::TestError {
+                        error: e,
+                        request_id,
+                    });
+                }
+            }
+        }
+        ApiJob::FetchModels {
+            endpoint, timeout, ..
+        } => {
+            let result = match client {
+                Ok(client) => {
+                    api::fetch_models(endpoint.build().as_ref(), &client, timeout, request_id).await
+                }
+                Err(e) => Err(e),
+            };
+            match result {
+                Ok(models) => {
+                    let _ = tx.send(ApiResponse::ModelsSuccess {
+                        models,
+                        provider: endpoint.provider,
+                        request_id,
+                    });
+                }
+                Err(e) => {
+                    let _ = tx.send(ApiResponse::ModelsError {
+                        error: e,
+                        provider: endpoint.provider,
+                        request_id,
+                    });
+                }
+            }
+        }
+        // Handled by the worker loop, which owns the task handles
+        ApiJob::Grammar { .. } | ApiJob::CancelGrammar | ApiJob::CancelParagraphs { .. } => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{MockResponse, MockServer};
+    use std::sync::mpsc;
+
+    const TEH: &str = r#"{"matches":[{"message":"Typo","original":"teh","replacement":"the","severity":"error"}]}"#;
+
+    /// A worker thread and a mock provider running on a runtime of its own.
+    fn start() -> (
+        tokio::runtime::Runtime,
+        MockServer,
+        Sender<ApiRequest>,
+        Receiver<ApiResponse>,
+    ) {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let server = rt.block_on(MockServer::start());
+        let (request_tx, request_rx) = mpsc::channel();
+        let (response_tx, response_rx) = mpsc::channel();
+        spawn_api_worker(request_rx, response_tx);
+        (rt, server, request_tx, response_rx)
+    }
+
+    fn grammar(server: &MockServer, paragraphs: &[(usize, &str)]) -> ApiRequest {
+        grammar_with_fallbacks(&[server], paragraphs)
+    }
+
+    /// A check trying each server in turn, as OpenAI model `mock-<index>`.
+    fn grammar_with_fallbacks(servers: &[&MockServer], paragraphs: &[(usize, &str)]) -> ApiRequest {
+        ApiRequest {
+            job: ApiJob::Grammar {
+                paragraphs: paragraphs
+                    .iter()
+                    .map(|(offset, text)| Paragraph {
+                        offset: *offset,
+                        text: text.to_string(),
+                    })
+                    .collect(),
+                backends: servers
+                    .iter()
+                    .enumerate()
+                    .map(|(i, server)| {
+                        let endpoint =
+                            Endpoint::new(ApiProvider::OpenAI, "key".to_string(), server.url());
+                        (endpoint, format!("mock-{}", i), None)
+                    })
+                    .collect(),
+                history: vec![],
+                network: NetworkSettings::default(),
+                options: Box::default(),
+            },
+            request_id: 3,
+        }
+    }
+
+    /// Responses up to and including the first one that ends a job.
+    fn collect(rx: &Receiver<ApiResponse>) -> Vec<ApiResponse> {
+        let mut responses = Vec::new();
+        loop {
+            let response = rx
+                .recv_timeout(Duration::from_secs(10))
+                .expect("worker did not answer");
+            let done = matches!(
+                response,
+                ApiResponse::GrammarSuccess { .. } | ApiResponse::GrammarError { .. }
+            );
+            responses.push(response);
+            if done {
+                return responses;
+            }
+        }
+    }
+
+    /// Suggestion offsets each paragraph was reported with, by its index.
+    fn reported(responses: &[ApiResponse]) -> Vec<(usize, Vec<usize>)> {
+        let mut reported: Vec<(usize, Vec<usize>)> = responses
+            .iter()
+            .filter_map(|r| match r {
+                ApiResponse::GrammarParagraph {
+                    paragraph,
+                    suggestions,
+                    ..
+                } => Some((*paragraph, suggestions.iter().map(|s| s.offset).collect())),
+                _ => None,
+            })
+            .collect();
+        reported.sort();
+        reported
+    }
+
+    #[test]
+    fn test_each_paragraph_is_reported() {
+        let (_rt, server, tx, rx) = start();
+        server.respond("/chat/completions", MockResponse::openai_chat(TEH));
+        server.respond("/chat/completions", MockResponse::openai_chat(TEH));
+
+        tx.send(grammar(&server, &[(0, "teh one"), (9, "and teh two")]))
+            .unwrap();
+        let responses = collect(&rx);
+
+        let usage_reports = responses
+            .iter()
+            .filter(|r| matches!(r, ApiResponse::GrammarUsage { .. }))
+            .count();
+        assert_eq!(usage_reports, 2);
+        // Offsets are relative to each paragraph
+        assert_eq!(reported(&responses), vec![(0, vec![0]), (1, vec![4])]);
+        assert!(matches!(
+            responses.last(),
+            Some(ApiResponse::GrammarSuccess { request_id: 3, .. })
+        ));
+    }
+
+    #[test]
+    fn test_failed_paragraph_keeps_the_others() {
+        let (_rt, server, tx, rx) = start();
+        server.respond("/chat/completions", MockResponse::openai_chat(TEH));
+        server.respond("/chat/completions", MockResponse::error(401, "Bad key"));
+
+        tx.send(grammar(&server, &[(0, "teh one"), (9, "and teh two")]))
+            .unwrap();
+        let responses = collect(&rx);
+
+        let reported = reported(&responses);
+        assert_eq!(reported.len(), 1);
+        assert_eq!(reported[0].1.len(), 1);
+        assert!(matches!(
+            responses.last(),
+            Some(ApiResponse::GrammarError { error, .. }) if error.needs_settings()
+        ));
+    }
+
+    #[test]
+    fn test_cancelled_paragraph_lets_the_others_finish() {
+        let (_rt, server, tx, rx) = start();
+        let slow = Duration::from_millis(500);
+        server.respond(
+            "/chat/completions",
+            MockResponse::openai_chat(TEH).delay(slow),
+        );
+        server.respond(
+            "/chat/completions",
+            MockResponse::openai_chat(TEH).delay(slow),
+        );
+
+        tx.send(grammar(&server, &[(0, "teh one"), (9, "and teh two")]))
+            .unwrap();
+        tx.send(ApiRequest {
+            job: ApiJob::CancelParagraphs {
+                check: 3,
+                paragraphs: vec![1],
+            },
+            request_id: 4,
+        })
+        .unwrap();
+        let responses = collect(&rx);
+
+        assert_eq!(reported(&responses), vec![(0, vec![0])]);
+        assert!(matches!(
+            responses.last(),
+            Some(ApiResponse::GrammarSuccess { request_id: 3, .. })
+        ));
+    }
+
+    #[test]
+    fn test_fallback_is_reported() {
+        let (rt, primary, tx, rx) = start();
+        let backup = rt.block_on(MockServer::start());
+        primary.respond(
+            "/chat/completions",
+            MockResponse::openai_chat("Sorry, I can't help with that."),
+        );
+        backup.respond("/chat/completions", MockResponse::openai_chat(TEH));
+
+        tx.send(grammar_with_fallbacks(
+            &[&primary, &backup],
+            &[(0, "teh one")],
+        ))
+        .unwrap();
+        let responses = collect(&rx);
+
+        assert!(responses.iter().any(|r| matches!(
+            r,
+            ApiResponse::GrammarFallback { to, .. } if to == "OpenAI · mock-1"
+        )));
+        let Some(ApiResponse::GrammarSuccess { sources, .. }) = responses.last() else {
+            panic!("expected success, got {:?}", responses.last());
+        };
+        assert_eq!(sources, &vec!["OpenAI · mock-1 (fallback)".to_string()]);
+    }
+
+    #[test]
+    fn test_client_is_rebuilt_only_for_new_settings() {
+        let mut shared = SharedClient::default();
+        let direct = NetworkSettings::default();
+        assert!(shared.get(&direct).is_ok());
+        assert!(shared.get(&direct).is_ok());
+
+        let broken = NetworkSettings {
+            ca_cert_file: "/nonexistent/ca.pem".to_string(),
+            ..NetworkSettings::default()
+        };
+        assert!(matches!(shared.get(&broken), Err(GrammyError::Client(_))));
+        // The working client is kept for jobs that still use it
+        assert_eq!(shared.current.as_ref().map(|(s, _)| s), Some(&direct));
+    }
+
+    #[test]
+    fn test_fetch_models() {
+        let (_rt, server, tx, rx) = start();
+        let models = serde_json::json!({ "data": [{ "id": "b" }, { "id": "a" }] });
+        server.respond("/models", MockResponse::json(200, models));
+
+        let endpoint = Endpoint::new(ApiProvider::Custom, String::new(), server.url());
+        tx.send(ApiRequest {
+            job: ApiJob::FetchModels {
+                endpoint,
+                network: NetworkSettings::default(),
+                timeout: Duration::from_secs(10),
+            },
+            request_id: 1,
+        })
+        .unwrap();
+
+        match rx.recv_timeout(Duration::from_secs(10)).unwrap() {
+            ApiResponse::ModelsSuccess {
+                models,
+                provider,
+                request_id,
+            } => {
+                assert_eq!(provider, ApiProvider::Custom);
+                assert_eq!(request_id, 1);
+                let ids: Vec<&str> = models.iter().map(|m| m.id.as_str()).collect();
+                assert_eq!(ids, vec!["a", "b"]);
+            }
+            other => panic!("expected models, got {:?}", other),
+        }
+    }
 }
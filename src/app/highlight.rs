@@ -24,6 +24,7 @@ pub enum Highlight {
     Error,      // Red - grammar errors, typos
     Warning,    // Orange - awkward phrasing
     Suggestion, // Yellow - minor improvements
+    Variant,    // Violet - spelling from another regional variety
     Hovered,    // Blue - currently hovered
 }
 
@@ -55,6 +56,7 @@ pub fn spans_from_suggestions(suggestions: &[Suggestion], hovered_id: Option<&st
                     Severity::Error => Highlight::Error,
                     Severity::Warning => Highlight::Warning,
                     Severity::Suggestion => Highlight::Suggestion,
+                    Severity::Variant => Highlight::Variant,
                 }
             };
 
@@ -178,6 +180,12 @@ pub fn to_format(highlight: &Highlight, _theme: &Theme) -> Format<Font> {
         b: 0.3,
         a: 1.0,
     }; // Yellow
+    let variant: Color = Color {
+        r: 0.75,
+        g: 0.52,
+        b: 0.99,
+        a: 1.0,
+    }; // Violet
     let hovered: Color = Color {
         r: 0.25,
         g: 0.75,
@@ -198,6 +206,10 @@ pub fn to_format(highlight: &Highlight, _theme: &Theme) -> Format<Font> {
             color: Some(suggestion),
             font: None,
         },
+        Highlight::Variant => Format {
+            color: Some(variant),
+            font: None,
+        },
         Highlight::Hovered => Format {
             color: Some(hovered),
             font: None,
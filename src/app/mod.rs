@@ -2,6 +2,8 @@ mod api_worker;
 mod draft;
 mod highlight;
 pub mod history;
+mod model_picker;
+mod paragraphs;
 mod state;
 mod style;
 mod ui;
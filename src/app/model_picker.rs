@@ -0,0 +1,185 @@
+//! The model picker in settings: listing entries with their metadata,
+//! filtering them, and keeping the last listings on disk.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::ApiProvider;
+use crate::provider::ModelInfo;
+use crate::usage::{self, format_cost, format_tokens};
+
+/// A model as shown in the picker: its ID followed by what is known about it.
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct ModelOption {
+    pub(super) info: ModelInfo,
+    /// Listed price, or the built-in one for providers that list none.
+    price_per_million: Option<(f64, f64)>,
+}
+
+impl ModelOption {
+    fn new(provider: &ApiProvider, info: &ModelInfo) -> Self {
+        let pricing = info
+            .pricing
+            .or_else(|| usage::list_price(provider, &info.id));
+        Self {
+            price_per_million: pricing
+                .map(|p| (p.prompt * 1_000_000.0, p.completion * 1_000_000.0)),
+            info: info.clone(),
+        }
+    }
+}
+
+impl fmt::Display for ModelOption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut details = Vec::new();
+        if let Some(tokens) = self.info.context_length {
+            details.push(format!("{} context", format_tokens(tokens)));
+        }
+        if let Some((prompt, completion)) = self.price_per_million {
+            details.push(if prompt == 0.0 && completion == 0.0 {
+                "free".to_string()
+            } else {
+                format!(
+                    "{} / {} per M",
+                    format_cost(prompt),
+                    format_cost(completion)
+                )
+            });
+        }
+        if self.info.structured_output == Some(true) {
+            details.push("JSON schema".to_string());
+        }
+        if let Some(modality) = &self.info.modality {
+            details.push(modality.clone());
+        }
+
+        if details.is_empty() {
+            write!(f, "{}", self.info.id)
+        } else {
+            write!(f, "{}  ·  {}", self.info.id, details.join(" · "))
+        }
+    }
+}
+
+/// Narrows the picker down. Only OpenRouter says which models support a JSON
+/// schema, so models that don't say are kept; models with no known price are
+/// left out while a price cap is set.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(super) struct ModelFilter {
+    pub(super) structured_output: bool,
+    /// Highest acceptable price in USD per million tokens, prompt and
+    /// completion alike, as typed; empty means no cap.
+    pub(super) max_price: String,
+}
+
+impl ModelFilter {
+    fn max_price(&self) -> Option<f64> {
+        self.max_price.trim().trim_start_matches('$').parse().ok()
+    }
+
+    fn matches(&self, option: &ModelOption) -> bool {
+        if self.structured_output && option.info.structured_output == Some(false) {
+            return false;
+        }
+        match (self.max_price(), option.price_per_million) {
+            (None, _) => true,
+            (Some(max), Some((prompt, completion))) => prompt.max(completion) <= max,
+            (Some(_), None) => false,
+        }
+    }
+}
+
+/// The picker entries for `models` that pass `filter`.
+pub(super) fn options(
+    provider: &ApiProvider,
+    models: &[ModelInfo],
+    filter: &ModelFilter,
+) -> Vec<ModelOption> {
+    models
+        .iter()
+        .map(|m| ModelOption::new(provider, m))
+        .filter(|o| filter.matches(o))
+        .collect()
+}
+
+/// Model listings as last fetched, so the picker is filled before the
+/// provider answers.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Listings {
+    #[serde(default)]
+    providers: HashMap<ApiProvider, Vec<ModelInfo>>,
+}
+
+pub(super) fn load() -> HashMap<ApiProvider, Vec<ModelInfo>> {
+    confy::load::<Listings>("grammy", "models")
+        .map(|l| l.providers)
+        .unwrap_or_default()
+}
+
+pub(super) fn save(models: &HashMap<ApiProvider, Vec<ModelInfo>>) {
+    let listings = Listings {
+        providers: models.clone(),
+    };
+    let _ = confy::store("grammy", "models", listings);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::usage::ModelPricing;
+
+    fn model(id: &str, prompt: f64, completion: f64, schema: Option<bool>) -> ModelInfo {
+        let mut info = ModelInfo::new(id);
+        info.pricing = Some(ModelPricing {
+            prompt: prompt / 1_000_000.0,
+            completion: completion / 1_000_000.0,
+        });
+        info.structured_output = schema;
+        info
+    }
+
+    #[test]
+    fn test_filters() {
+        let models = [
+            model("cheap", 0.1, 0.4, Some(true)),
+            model("pricey", 3.0, 15.0, Some(true)),
+            model("plain", 0.1, 0.2, Some(false)),
+            ModelInfo::new("unknown"),
+        ];
+        let ids = |filter: &ModelFilter| -> Vec<String> {
+            options(&ApiProvider::OpenRouter, &models, filter)
+                .into_iter()
+                .map(|o| o.info.id)
+                .collect()
+        };
+
+        assert_eq!(ids(&ModelFilter::default()).len(), 4);
+        let schema = ModelFilter {
+            structured_output: true,
+            ..Default::default()
+        };
+        assert_eq!(ids(&schema), vec!["cheap", "pricey", "unknown"]);
+        let cheap = ModelFilter {
+            max_price: "$1".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(ids(&cheap), vec!["cheap", "plain"]);
+    }
+
+    #[test]
+    fn test_display() {
+        let mut info = model("vendor/model", 0.15, 0.6, Some(true));
+        info.context_length = Some(128_000);
+        info.modality = Some("text->text".to_string());
+        assert_eq!(
+            ModelOption::new(&ApiProvider::OpenRouter, &info).to_string(),
+            "vendor/model  ·  128.0k context · $0.15 / $0.60 per M · JSON schema · text->text"
+        );
+
+        // Built-in prices stand in for providers that list none
+        let listed = ModelOption::new(&ApiProvider::OpenAI, &ModelInfo::new("gpt-4o-mini"));
+        assert_eq!(listed.to_string(), "gpt-4o-mini  ·  $0.15 / $0.60 per M");
+    }
+}
@@ -0,0 +1,162 @@
+//! Splitting the document into independently checkable paragraphs, and
+//! keeping byte offsets valid across edits.
+
+/// A run of non-blank lines, located by its byte offset in the document.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Paragraph {
+    pub offset: usize,
+    pub text: String,
+}
+
+impl Paragraph {
+    pub fn end(&self) -> usize {
+        self.offset + self.text.len()
+    }
+
+    /// Whether the byte range `offset..offset + length` lies within this paragraph.
+    pub fn contains(&self, offset: usize, length: usize) -> bool {
+        offset >= self.offset && offset + length <= self.end()
+    }
+}
+
+/// Split `text` into paragraphs separated by blank lines.
+pub fn split(text: &str) -> Vec<Paragraph> {
+    let mut paragraphs = Vec::new();
+    let mut current: Option<(usize, usize)> = None; // (start, end) of the open paragraph
+    let mut line_start = 0;
+
+    for line in text.split_inclusive('\n') {
+        let content = line.trim_end_matches(['\n', '\r']);
+        if content.trim().is_empty() {
+            if let Some((start, end)) = current.take() {
+                paragraphs.push(Paragraph {
+                    offset: start,
+                    text: text[start..end].to_string(),
+                });
+            }
+        } else {
+            let end = line_start + content.len();
+            current = Some((current.map_or(line_start, |(start, _)| start), end));
+        }
+        line_start += line.len();
+    }
+
+    if let Some((start, end)) = current {
+        paragraphs.push(Paragraph {
+            offset: start,
+            text: text[start..end].to_string(),
+        });
+    }
+
+    paragraphs
+}
+
+/// The single contiguous change between two versions of the document:
+/// `old[start..old_end]` was replaced by `new[start..new_end]`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextEdit {
+    pub start: usize,
+    pub old_end: usize,
+    pub new_end: usize,
+}
+
+impl TextEdit {
+    pub fn between(old: &str, new: &str) -> Self {
+        let (old_bytes, new_bytes) = (old.as_bytes(), new.as_bytes());
+
+        let mut prefix = old_bytes
+            .iter()
+            .zip(new_bytes)
+            .take_while(|(a, b)| a == b)
+            .count();
+        // The shared bytes are identical, so a boundary in one is a boundary in both
+        while !old.is_char_boundary(prefix) {
+            prefix -= 1;
+        }
+
+        let max_suffix = old.len().min(new.len()) - prefix;
+        let mut suffix = old_bytes
+            .iter()
+            .rev()
+            .zip(new_bytes.iter().rev())
+            .take(max_suffix)
+            .take_while(|(a, b)| a == b)
+            .count();
+        while !old.is_char_boundary(old.len() - suffix) {
+            suffix -= 1;
+        }
+
+        Self {
+            start: prefix,
+            old_end: old.len() - suffix,
+            new_end: new.len() - suffix,
+        }
+    }
+
+    /// Where the range `offset..offset + length` ends up after the edit, or
+    /// `None` if the edit touches it.
+    pub fn shift(&self, offset: usize, length: usize) -> Option<usize> {
+        if offset + length < self.start {
+            Some(offset)
+        } else if offset > self.old_end {
+            Some(offset + self.new_end - self.old_end)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_on_blank_lines() {
+        let text = "First line\nstill first.\n\n  \nSecond.\r\n\nThird";
+        let paragraphs = split(text);
+        assert_eq!(paragraphs.len(), 3);
+        assert_eq!(paragraphs[0].text, "First line\nstill first.");
+        assert_eq!(paragraphs[0].offset, 0);
+        assert_eq!(paragraphs[1].text, "Second.");
+        assert_eq!(&text[paragraphs[1].offset..paragraphs[1].end()], "Second.");
+        assert_eq!(paragraphs[2].text, "Third");
+        assert!(split("\n\n  \n").is_empty());
+    }
+
+    #[test]
+    fn test_edit_between() {
+        let edit = TextEdit::between("I has a cat.", "I have a cat.");
+        assert_eq!(
+            edit,
+            TextEdit {
+                start: 4,
+                old_end: 5,
+                new_end: 6
+            }
+        );
+
+        // Multi-byte characters are never split
+        let edit = TextEdit::between("café au lait", "cafè au lait");
+        assert_eq!(
+            edit,
+            TextEdit {
+                start: 3,
+                old_end: 5,
+                new_end: 5
+            }
+        );
+    }
+
+    #[test]
+    fn test_shift() {
+        // "Hello wrld. I has a cat." -> "Hello world. I has a cat."
+        let edit = TextEdit::between("Hello wrld. I has a cat.", "Hello world. I has a cat.");
+        assert_eq!(edit.shift(14, 3), Some(15)); // "has" moves right
+        assert_eq!(edit.shift(6, 4), None); // "wrld" was edited
+        assert_eq!(edit.shift(0, 5), Some(0)); // "Hello" is before the edit
+
+        // Typing right after a word touches it
+        let edit = TextEdit::between("I has", "I hass");
+        assert_eq!(edit.shift(2, 3), None);
+    }
+}
@@ -1,15 +1,30 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
 use std::time::{Duration, Instant};
 
 use iced::widget::text_editor;
 use iced::{window, Subscription, Task, Theme};
 
-use crate::config::{ApiProvider, Config};
+use crate::api::{shift_suggestions, CheckOptions};
+use crate::cache::CheckCache;
+use crate::config::{ApiProvider, Config, Fallback};
+use crate::dialect::Dialect;
+use crate::dictionary;
+use crate::error::GrammyError;
+use crate::language::{self, Lang, LanguageSetting};
+use crate::network::NetworkSettings;
+use crate::prompt::{Prompt, DEFAULT_TEMPLATE_NAME};
+use crate::provider::{Endpoint, ModelInfo};
+use crate::style_guide::{self, StyleGuide};
 use crate::suggestion::Suggestion;
+use crate::usage::{self, BudgetUnit, Usage, UsageLog, UsageTotals};
 
 use super::api_worker::{spawn_api_worker, ApiJob, ApiRequest, ApiResponse};
 use super::draft;
 use super::history::MessageHistory;
+use super::model_picker::{self, ModelFilter, ModelOption};
+use super::paragraphs::{self, Paragraph, TextEdit};
 use super::style;
 use super::ui;
 
@@ -26,123 +41,255 @@ pub enum Message {
     EditorAction(text_editor::Action),
     ApplySuggestion(String),
     DismissSuggestion(String),
+    AddToDictionary(String),
     HoverSuggestion(String),
     ClearHoverSuggestion,
 
     ForceCheck,
+    PromptTemplateSelected(String),
+    ConfirmForceCheck,
+    CancelForceCheck,
 
     OpenSettings,
     CloseSettings,
+    OpenStats,
+    CloseStats,
     ToggleShowApiKey,
 
     SelectProvider(ApiProvider),
-    TempOpenAiKeyChanged(String),
-    TempOpenRouterKeyChanged(String),
-    TempGeminiKeyChanged(String),
+    TempApiKeyChanged(String),
+    TempCustomBaseUrlChanged(String),
     TempModelChanged(String),
     TempDebounceChanged(f32),
+    TempStreamResponsesToggled(bool),
+    TempCacheEnabledToggled(bool),
+    TempBudgetUnitSelected(BudgetUnit),
+    TempLanguageSelected(LanguageSetting),
+    TempDialectSelected(Dialect),
+    TempDailyBudgetChanged(String),
+    TempMonthlyBudgetChanged(String),
+    TempHttpProxyChanged(String),
+    TempHttpsProxyChanged(String),
+    TempProxyUsernameChanged(String),
+    TempProxyPasswordChanged(String),
+    TempNoProxyChanged(String),
+    TempCaCertFileChanged(String),
+    NewFallbackProviderSelected(ApiProvider),
+    NewFallbackModelChanged(String),
+    AddFallback,
+    RemoveFallback(usize),
+    RemoveDictionaryWord(usize),
+    ClearCache,
     ModelSelected(String),
+    ModelFilterStructuredOutputToggled(bool),
+    ModelFilterMaxPriceChanged(String),
 
     SaveSettings,
     StartTestConnection,
 }
 
+/// A paragraph being checked, named as in its check's job.
+pub(super) struct PendingParagraph {
+    check: u64,
+    index: usize,
+    paragraph: Paragraph,
+}
+
 pub struct State {
     pub(super) editor: text_editor::Content,
-    pub(super) last_checked_text: String,
     pub(super) suggestions: Vec<Suggestion>,
+    /// Paragraphs whose suggestions are in `suggestions`, kept in step with edits.
+    pub(super) checked_paragraphs: Vec<Paragraph>,
 
     pub(super) draft_dirty: bool,
+    /// File given on the command line, whose text was loaded instead of the
+    /// draft. It is never written to, and the draft is left alone meanwhile.
+    pub(super) opened_file: Option<PathBuf>,
+
+    /// The `.grammy.toml` in effect, if one was found.
+    pub(super) style_guide_path: Option<PathBuf>,
+    pub(super) style_guide: StyleGuide,
+    /// Why the style guide could not be read; its rules are not applied.
+    pub(super) style_guide_error: Option<String>,
 
     pub(super) hovered_suggestion: Option<String>,
 
     pub(super) status: String,
+    /// The failure behind `status`, if it describes one.
+    pub(super) error: Option<GrammyError>,
 
     pub(super) config: Config,
 
     pub(super) show_settings: bool,
     pub(super) show_api_key: bool,
-    pub(super) temp_openai_api_key: String,
-    pub(super) temp_openrouter_api_key: String,
-    pub(super) temp_gemini_api_key: String,
+    pub(super) temp_api_keys: HashMap<ApiProvider, String>,
+    pub(super) temp_custom_base_url: String,
     pub(super) temp_model: String,
     pub(super) temp_provider: ApiProvider,
     pub(super) temp_debounce_ms: f32,
-
-    pub(super) openai_models: Vec<String>,
-    pub(super) openrouter_models: Vec<String>,
-    pub(super) gemini_models: Vec<String>,
-    pub(super) model_combo_state: iced::widget::combo_box::State<String>,
+    pub(super) temp_stream_responses: bool,
+    pub(super) temp_cache_enabled: bool,
+    pub(super) temp_budget_unit: BudgetUnit,
+    pub(super) temp_language: LanguageSetting,
+    pub(super) temp_dialect: Dialect,
+    pub(super) temp_daily_budget: String,
+    pub(super) temp_monthly_budget: String,
+    pub(super) temp_network: NetworkSettings,
+    pub(super) temp_fallbacks: Vec<Fallback>,
+    /// The fallback being entered below the list, not yet added.
+    pub(super) new_fallback_provider: ApiProvider,
+    pub(super) new_fallback_model: String,
+    pub(super) temp_dictionary: Vec<String>,
+    /// Bytes used by the result cache, measured when settings open.
+    pub(super) cache_size: u64,
+    /// The result cache every check goes through, so what it knows about its
+    /// size carries over; see [`result_cache`].
+    pub(super) cache: Option<CheckCache>,
+
+    /// Model listings per provider, kept on disk between runs.
+    pub(super) models: HashMap<ApiProvider, Vec<ModelInfo>>,
+    /// The latest model listing asked for per provider; answers to older
+    /// ones, e.g. made with a previous API key, are dropped.
+    pub(super) current_models_request_ids: HashMap<ApiProvider, u64>,
+    pub(super) model_filter: ModelFilter,
+    /// The current provider's models that pass the filter.
+    pub(super) model_options: Vec<ModelOption>,
+    pub(super) model_combo_state: iced::widget::combo_box::State<ModelOption>,
 
     pub(super) test_status: String,
+    pub(super) test_error: Option<GrammyError>,
     pub(super) is_testing: bool,
     pub(super) current_test_request_id: Option<u64>,
 
     pub(super) last_edit_time: Option<Instant>,
-    pub(super) is_checking: bool,
-    pub(super) current_check_request_id: Option<u64>,
-    pub(super) pending_recheck: bool,
-    pub(super) pending_check_text: Option<String>,
+    /// Request ids of the checks still running, oldest first. Each covers
+    /// only the paragraphs edited since the ones before it were sent.
+    pub(super) running_checks: Vec<u64>,
+    /// Paragraphs sent to be checked and not yet answered for, kept in step
+    /// with edits.
+    pub(super) pending_paragraphs: Vec<PendingParagraph>,
+    /// Unreadable entries given up on during the running checks.
+    pub(super) dropped_matches: usize,
+    /// Backends that answered the latest check, for the sidebar.
+    pub(super) checked_with: Vec<String>,
+    /// Language the latest check asked for, for the status bar.
+    pub(super) checked_language: Option<Lang>,
 
     pub(super) message_history: MessageHistory,
 
+    pub(super) show_stats: bool,
+    /// Usage of the latest grammar check.
+    pub(super) check_usage: UsageTotals,
+    pub(super) session_usage: UsageTotals,
+    pub(super) usage_log: UsageLog,
+    /// Why a manual check needs confirming, while the question is shown.
+    pub(super) confirm_over_budget: Option<String>,
+
     pub(super) api_sender: Sender<ApiRequest>,
     pub(super) api_receiver: Receiver<ApiResponse>,
 }
 
-pub fn new() -> (State, Task<Message>) {
+/// Start the app, editing the text of `file` if one is given.
+pub fn new(file: Option<PathBuf>) -> (State, Task<Message>) {
     let config = Config::load();
 
     let (request_tx, request_rx) = channel::<ApiRequest>();
     let (response_tx, response_rx) = channel::<ApiResponse>();
     spawn_api_worker(request_rx, response_tx);
 
-    let draft = draft::load();
-    let editor = if draft.text.is_empty() {
+    let opened = file.map(|path| {
+        let text = std::fs::read_to_string(&path);
+        (path, text)
+    });
+    let text = match &opened {
+        Some((_, Ok(text))) => text.clone(),
+        _ => draft::load().text,
+    };
+    let editor = if text.is_empty() {
         text_editor::Content::new()
     } else {
-        text_editor::Content::with_text(&draft.text)
+        text_editor::Content::with_text(&text)
     };
 
-    (
-        State {
-            editor,
-            last_checked_text: String::new(),
-            suggestions: Vec::new(),
-
-            draft_dirty: false,
-
-            hovered_suggestion: None,
-            status: "Ready".to_string(),
-            config: config.clone(),
-            show_settings: false,
-            show_api_key: false,
-            temp_openai_api_key: config.openai_api_key.clone(),
-            temp_openrouter_api_key: config.openrouter_api_key.clone(),
-            temp_gemini_api_key: config.gemini_api_key.clone(),
-            temp_model: config.model,
-            temp_provider: config.provider,
-            temp_debounce_ms: config.debounce_ms as f32,
-
-            openai_models: Vec::new(),
-            openrouter_models: Vec::new(),
-            gemini_models: Vec::new(),
-            model_combo_state: iced::widget::combo_box::State::new(Vec::new()),
-
-            test_status: String::new(),
-            is_testing: false,
-            current_test_request_id: None,
-            last_edit_time: None,
-            is_checking: false,
-            current_check_request_id: None,
-            pending_recheck: false,
-            pending_check_text: None,
-            message_history: MessageHistory::default(),
-            api_sender: request_tx,
-            api_receiver: response_rx,
-        },
-        Task::none(),
-    )
+    let cache = config.cache();
+    let mut state = State {
+        editor,
+        suggestions: Vec::new(),
+        checked_paragraphs: Vec::new(),
+
+        draft_dirty: false,
+        opened_file: None,
+
+        style_guide_path: None,
+        style_guide: StyleGuide::default(),
+        style_guide_error: None,
+
+        hovered_suggestion: None,
+        status: "Ready".to_string(),
+        error: None,
+        config: config.clone(),
+        show_settings: false,
+        show_api_key: false,
+        temp_api_keys: api_keys(&config),
+        temp_custom_base_url: config.custom_base_url.clone(),
+        temp_model: config.model,
+        temp_provider: config.provider,
+        temp_debounce_ms: config.debounce_ms as f32,
+        temp_stream_responses: config.stream_responses,
+        temp_cache_enabled: config.cache_enabled,
+        temp_budget_unit: config.budget_unit,
+        temp_language: config.language,
+        temp_dialect: config.dialect,
+        temp_daily_budget: budget_text(config.daily_budget),
+        temp_monthly_budget: budget_text(config.monthly_budget),
+        temp_network: config.network.clone(),
+        temp_fallbacks: config.fallbacks.clone(),
+        new_fallback_provider: ApiProvider::OpenAI,
+        new_fallback_model: String::new(),
+        temp_dictionary: config.dictionary.clone(),
+        cache_size: 0,
+        cache,
+
+        models: model_picker::load(),
+        current_models_request_ids: HashMap::new(),
+        model_filter: ModelFilter::default(),
+        model_options: Vec::new(),
+        model_combo_state: iced::widget::combo_box::State::new(Vec::new()),
+
+        test_status: String::new(),
+        test_error: None,
+        is_testing: false,
+        current_test_request_id: None,
+        last_edit_time: None,
+        running_checks: Vec::new(),
+        pending_paragraphs: Vec::new(),
+        dropped_matches: 0,
+        checked_with: Vec::new(),
+        checked_language: None,
+        message_history: MessageHistory::default(),
+        show_stats: false,
+        check_usage: UsageTotals::default(),
+        session_usage: UsageTotals::default(),
+        usage_log: UsageLog::load(),
+        confirm_over_budget: None,
+        api_sender: request_tx,
+        api_receiver: response_rx,
+    };
+
+    match opened {
+        Some((path, Ok(_))) => state.opened_file = Some(path),
+        Some((path, Err(e))) => set_error(
+            &mut state,
+            GrammyError::Internal(format!("could not open {} ({})", path.display(), e)),
+        ),
+        None => {}
+    }
+    load_style_guide(&mut state);
+
+    // The model listing carries prices for usage accounting
+    fetch_models_if_needed(&mut state);
+
+    (state, Task::none())
 }
 
 pub fn update(state: &mut State, message: Message) -> Task<Message> {
@@ -174,15 +321,11 @@ pub fn update(state: &mut State, message: Message) -> Task<Message> {
             state.editor.perform(action);
             let new_text = state.editor.text();
 
-            // Only clear suggestions if text actually changed
+            // Only touch suggestions if text actually changed
             if old_text != new_text {
-                state.suggestions.clear();
-                state.hovered_suggestion = None;
+                shift_for_edit(state, TextEdit::between(&old_text, &new_text));
                 state.last_edit_time = Some(Instant::now());
-                state.draft_dirty = true;
-                if state.is_checking {
-                    state.pending_recheck = true;
-                }
+                state.draft_dirty = state.opened_file.is_none();
             }
             Task::none()
         }
@@ -191,7 +334,7 @@ pub fn update(state: &mut State, message: Message) -> Task<Message> {
             let old_text = state.editor.text();
             apply_suggestion(state, &id);
             if state.editor.text() != old_text {
-                state.draft_dirty = true;
+                state.draft_dirty = state.opened_file.is_none();
             }
             Task::none()
         }
@@ -202,17 +345,45 @@ pub fn update(state: &mut State, message: Message) -> Task<Message> {
                 state.hovered_suggestion = None;
             }
 
-            if !state.is_checking {
+            if state.running_checks.is_empty() {
                 if state.suggestions.is_empty() {
-                    state.status = "All good!".to_string();
+                    set_status(state, "All good!");
                 } else {
-                    state.status = format!("{} suggestion(s)", state.suggestions.len());
+                    set_status(state, format!("{} suggestion(s)", state.suggestions.len()));
                 }
             }
 
             Task::none()
         }
 
+        Message::AddToDictionary(id) => {
+            let Some(original) = state
+                .suggestions
+                .iter()
+                .find(|s| s.id == id)
+                .map(|s| s.original.clone())
+            else {
+                return Task::none();
+            };
+            if let Some(word) = dictionary::add(&mut state.config.dictionary, &original) {
+                // Saving the settings writes their copy back, so it needs the word too
+                dictionary::add(&mut state.temp_dictionary, &word);
+                state.config.save();
+                // Other places the word was flagged go too
+                let known = &state.config.dictionary;
+                state
+                    .suggestions
+                    .retain(|s| !dictionary::contains(known, &s.original));
+                if let Some(id) = &state.hovered_suggestion {
+                    if !state.suggestions.iter().any(|s| &s.id == id) {
+                        state.hovered_suggestion = None;
+                    }
+                }
+                set_status(state, format!("Added \"{}\" to the dictionary", word));
+            }
+            Task::none()
+        }
+
         Message::HoverSuggestion(id) => {
             state.hovered_suggestion = Some(id);
             Task::none()
@@ -224,24 +395,50 @@ pub fn update(state: &mut State, message: Message) -> Task<Message> {
         }
 
         Message::ForceCheck => {
-            if state.is_checking {
-                state.pending_recheck = true;
-                state.status = "Rechecking...".to_string();
+            if let Some(reason) = state.config.budget().exceeded(&state.usage_log) {
+                state.confirm_over_budget = Some(reason);
                 return Task::none();
             }
-
-            state.last_checked_text.clear();
-            check_text(state);
+            force_check(state);
+            Task::none()
+        }
+        Message::PromptTemplateSelected(name) => {
+            state.config.prompt_template = if name == DEFAULT_TEMPLATE_NAME {
+                String::new()
+            } else {
+                name
+            };
+            state.config.save();
+            Task::none()
+        }
+        Message::ConfirmForceCheck => {
+            state.confirm_over_budget = None;
+            force_check(state);
+            Task::none()
+        }
+        Message::CancelForceCheck => {
+            state.confirm_over_budget = None;
             Task::none()
         }
 
         Message::OpenSettings => {
-            state.temp_openai_api_key = state.config.openai_api_key.clone();
-            state.temp_openrouter_api_key = state.config.openrouter_api_key.clone();
-            state.temp_gemini_api_key = state.config.gemini_api_key.clone();
+            state.temp_api_keys = api_keys(&state.config);
+            state.temp_custom_base_url = state.config.custom_base_url.clone();
             state.temp_model = state.config.model.clone();
             state.temp_provider = state.config.provider.clone();
             state.temp_debounce_ms = state.config.debounce_ms as f32;
+            state.temp_stream_responses = state.config.stream_responses;
+            state.temp_cache_enabled = state.config.cache_enabled;
+            state.temp_budget_unit = state.config.budget_unit;
+            state.temp_language = state.config.language;
+            state.temp_dialect = state.config.dialect;
+            state.temp_daily_budget = budget_text(state.config.daily_budget);
+            state.temp_monthly_budget = budget_text(state.config.monthly_budget);
+            state.temp_network = state.config.network.clone();
+            state.temp_fallbacks = state.config.fallbacks.clone();
+            state.new_fallback_model.clear();
+            state.temp_dictionary = state.config.dictionary.clone();
+            state.cache_size = result_cache(state).map_or(0, |c| c.size());
             state.show_api_key = false;
             state.test_status.clear();
             state.show_settings = true;
@@ -254,6 +451,14 @@ pub fn update(state: &mut State, message: Message) -> Task<Message> {
             state.show_settings = false;
             Task::none()
         }
+        Message::OpenStats => {
+            state.show_stats = true;
+            Task::none()
+        }
+        Message::CloseStats => {
+            state.show_stats = false;
+            Task::none()
+        }
         Message::ToggleShowApiKey => {
             state.show_api_key = !state.show_api_key;
             Task::none()
@@ -268,18 +473,15 @@ pub fn update(state: &mut State, message: Message) -> Task<Message> {
             Task::none()
         }
 
-        Message::TempOpenAiKeyChanged(v) => {
-            state.temp_openai_api_key = v;
+        Message::TempApiKeyChanged(v) => {
+            state.temp_api_keys.insert(state.temp_provider.clone(), v);
             fetch_models_if_needed(state);
             Task::none()
         }
-        Message::TempOpenRouterKeyChanged(v) => {
-            state.temp_openrouter_api_key = v;
-            fetch_models_if_needed(state);
-            Task::none()
-        }
-        Message::TempGeminiKeyChanged(v) => {
-            state.temp_gemini_api_key = v;
+        Message::TempCustomBaseUrlChanged(v) => {
+            state.temp_custom_base_url = v;
+            // A different server serves a different set of models.
+            state.models.remove(&ApiProvider::Custom);
             fetch_models_if_needed(state);
             Task::none()
         }
@@ -291,15 +493,125 @@ pub fn update(state: &mut State, message: Message) -> Task<Message> {
             state.temp_debounce_ms = v;
             Task::none()
         }
+        Message::TempStreamResponsesToggled(v) => {
+            state.temp_stream_responses = v;
+            Task::none()
+        }
+        Message::TempCacheEnabledToggled(v) => {
+            state.temp_cache_enabled = v;
+            Task::none()
+        }
+        Message::TempBudgetUnitSelected(v) => {
+            state.temp_budget_unit = v;
+            Task::none()
+        }
+        Message::TempLanguageSelected(v) => {
+            state.temp_language = v;
+            Task::none()
+        }
+        Message::TempDialectSelected(v) => {
+            state.temp_dialect = v;
+            Task::none()
+        }
+        Message::TempDailyBudgetChanged(v) => {
+            state.temp_daily_budget = v;
+            Task::none()
+        }
+        Message::TempMonthlyBudgetChanged(v) => {
+            state.temp_monthly_budget = v;
+            Task::none()
+        }
+        Message::TempHttpProxyChanged(v) => {
+            state.temp_network.http_proxy = v;
+            Task::none()
+        }
+        Message::TempHttpsProxyChanged(v) => {
+            state.temp_network.https_proxy = v;
+            Task::none()
+        }
+        Message::TempProxyUsernameChanged(v) => {
+            state.temp_network.proxy_username = v;
+            Task::none()
+        }
+        Message::TempProxyPasswordChanged(v) => {
+            state.temp_network.proxy_password = v;
+            Task::none()
+        }
+        Message::TempNoProxyChanged(v) => {
+            state.temp_network.no_proxy = v;
+            Task::none()
+        }
+        Message::TempCaCertFileChanged(v) => {
+            state.temp_network.ca_cert_file = v;
+            Task::none()
+        }
+        Message::NewFallbackProviderSelected(p) => {
+            state.new_fallback_provider = p;
+            Task::none()
+        }
+        Message::NewFallbackModelChanged(v) => {
+            state.new_fallback_model = v;
+            Task::none()
+        }
+        Message::AddFallback => {
+            let model = match state.new_fallback_model.trim() {
+                "" => state.new_fallback_provider.default_model(),
+                model => model,
+            };
+            if !model.is_empty() {
+                state.temp_fallbacks.push(Fallback {
+                    provider: state.new_fallback_provider.clone(),
+                    model: model.to_string(),
+                });
+                state.new_fallback_model.clear();
+            }
+            Task::none()
+        }
+        Message::RemoveDictionaryWord(index) => {
+            if index < state.temp_dictionary.len() {
+                state.temp_dictionary.remove(index);
+            }
+            Task::none()
+        }
+        Message::RemoveFallback(index) => {
+            if index < state.temp_fallbacks.len() {
+                state.temp_fallbacks.remove(index);
+            }
+            Task::none()
+        }
+        Message::ClearCache => {
+            if let Some(cache) = result_cache(state) {
+                if let Err(e) = cache.clear() {
+                    eprintln!("[DEBUG] Failed to clear cache: {}", e);
+                }
+                state.cache_size = cache.size();
+            }
+            Task::none()
+        }
         Message::ModelSelected(v) => {
             state.temp_model = v;
             Task::none()
         }
+        Message::ModelFilterStructuredOutputToggled(v) => {
+            state.model_filter.structured_output = v;
+            refresh_model_options(state);
+            Task::none()
+        }
+        Message::ModelFilterMaxPriceChanged(v) => {
+            state.model_filter.max_price = v;
+            refresh_model_options(state);
+            Task::none()
+        }
 
         Message::SaveSettings => {
-            state.config.openai_api_key = state.temp_openai_api_key.trim().to_string();
-            state.config.openrouter_api_key = state.temp_openrouter_api_key.trim().to_string();
-            state.config.gemini_api_key = state.temp_gemini_api_key.trim().to_string();
+            for provider in ApiProvider::ALL {
+                let key = state
+                    .temp_api_keys
+                    .get(&provider)
+                    .map(|k| k.trim().to_string());
+                state.config.set_api_key(&provider, key.unwrap_or_default());
+            }
+            state.config.custom_base_url = state.temp_custom_base_url.trim().to_string();
             state.config.provider = state.temp_provider.clone();
             state.config.model = if state.temp_model.trim().is_empty() {
                 state.config.provider.default_model().to_string()
@@ -307,9 +619,19 @@ pub fn update(state: &mut State, message: Message) -> Task<Message> {
                 state.temp_model.trim().to_string()
             };
             state.config.debounce_ms = state.temp_debounce_ms as u64;
+            state.config.stream_responses = state.temp_stream_responses;
+            state.config.cache_enabled = state.temp_cache_enabled;
+            state.config.budget_unit = state.temp_budget_unit;
+            state.config.language = state.temp_language;
+            state.config.dialect = state.temp_dialect;
+            state.config.daily_budget = parse_budget(&state.temp_daily_budget);
+            state.config.monthly_budget = parse_budget(&state.temp_monthly_budget);
+            state.config.network = state.temp_network.clone();
+            state.config.fallbacks = state.temp_fallbacks.clone();
+            state.config.dictionary = state.temp_dictionary.clone();
             state.config.save();
             state.show_settings = false;
-            state.status = "Settings saved".to_string();
+            set_status(state, "Settings saved");
             Task::none()
         }
 
@@ -322,18 +644,14 @@ pub fn update(state: &mut State, message: Message) -> Task<Message> {
             state.is_testing = true;
             state.current_test_request_id = Some(request_id);
             state.test_status = "Testing...".to_string();
-
-            let api_key = match state.temp_provider {
-                ApiProvider::OpenAI => state.temp_openai_api_key.trim().to_string(),
-                ApiProvider::OpenRouter => state.temp_openrouter_api_key.trim().to_string(),
-                ApiProvider::Gemini => state.temp_gemini_api_key.trim().to_string(),
-            };
+            state.test_error = None;
 
             let request = ApiRequest {
                 job: ApiJob::TestConnection {
-                    api_key,
-                    provider: state.temp_provider.clone(),
+                    endpoint: temp_endpoint(state),
                     model: state.temp_model.clone(),
+                    network: state.temp_network.clone(),
+                    timeout: Duration::from_secs(state.config.test_timeout_secs),
                 },
                 request_id,
             };
@@ -341,7 +659,9 @@ pub fn update(state: &mut State, message: Message) -> Task<Message> {
             if let Err(e) = state.api_sender.send(request) {
                 state.is_testing = false;
                 state.current_test_request_id = None;
-                state.test_status = format!("Internal error: failed to send test ({})", e);
+                let error = GrammyError::Internal(format!("failed to send test ({})", e));
+                state.test_status = error.to_string();
+                state.test_error = Some(error);
             }
 
             Task::none()
@@ -385,146 +705,442 @@ fn tick_debounce(state: &mut State) {
         }
         if edit_time.elapsed() >= Duration::from_millis(delay) {
             state.last_edit_time = None;
-            check_text(state);
+            let paused = state.config.budget().exceeded(&state.usage_log);
+            check_text(state, paused);
+        }
+    }
+}
+
+/// The long-lived result cache, rebuilt only when its size limit changed.
+fn result_cache(state: &mut State) -> Option<CheckCache> {
+    let configured = state.config.cache();
+    if state.cache.as_ref().map(CheckCache::max_bytes)
+        != configured.as_ref().map(CheckCache::max_bytes)
+    {
+        state.cache = configured;
+    }
+    state.cache.clone()
+}
+
+/// Check the whole document again, superseding a check that is still running.
+/// The style guide is read again too, in case it was edited meanwhile.
+fn force_check(state: &mut State) {
+    load_style_guide(state);
+    if !state.running_checks.is_empty() {
+        cancel_check(state);
+    }
+    state.checked_paragraphs.clear();
+    check_text(state, None);
+}
+
+/// Find and read the `.grammy.toml` nearest to the opened file, or to the
+/// working directory when editing a draft.
+fn load_style_guide(state: &mut State) {
+    let start = match &state.opened_file {
+        Some(file) => file.parent().map(Path::to_path_buf),
+        None => std::env::current_dir().ok(),
+    };
+    state.style_guide_path = start.as_deref().and_then(style_guide::discover);
+    let loaded = match &state.style_guide_path {
+        Some(path) => StyleGuide::load(path).map_err(|e| format!("{} ({})", path.display(), e)),
+        None => Ok(StyleGuide::default()),
+    };
+    match loaded {
+        Ok(guide) => {
+            state.style_guide = guide;
+            state.style_guide_error = None;
+        }
+        Err(e) => {
+            state.style_guide = StyleGuide::default();
+            state.style_guide_error = Some(e);
         }
     }
 }
 
-fn check_text(state: &mut State) {
+/// Check the paragraphs changed since their last check. With `paused` set,
+/// the reason the budget ran out, only the local checks run and the model is
+/// left alone; the paragraphs stay unchecked for when it can be asked again.
+fn check_text(state: &mut State, paused: Option<String>) {
     let text = state.editor.text();
 
     if text.trim().is_empty() {
         state.suggestions.clear();
+        state.checked_paragraphs.clear();
         state.hovered_suggestion = None;
-        state.status = "Ready".to_string();
-        state.last_checked_text = text;
-        state.is_checking = false;
-        state.current_check_request_id = None;
+        set_status(state, "Ready");
+        if !state.running_checks.is_empty() {
+            cancel_check(state);
+        }
         return;
     }
 
-    if text == state.last_checked_text {
+    // Only paragraphs that changed since their last check, and are not
+    // being checked already, are sent
+    let changed: Vec<Paragraph> = paragraphs::split(&text)
+        .into_iter()
+        .filter(|p| !state.checked_paragraphs.contains(p))
+        .filter(|p| !state.pending_paragraphs.iter().any(|q| &q.paragraph == p))
+        .collect();
+    if changed.is_empty() {
         return;
     }
 
-    if state.is_checking {
-        state.pending_recheck = true;
+    // The paragraphs being re-checked will report their suggestions afresh
+    state
+        .suggestions
+        .retain(|s| !changed.iter().any(|p| p.contains(s.offset, s.length)));
+    if let Some(id) = &state.hovered_suggestion {
+        if !state.suggestions.iter().any(|s| &s.id == id) {
+            state.hovered_suggestion = None;
+        }
+    }
+
+    // Banned and preferred terms show up right away, whatever the model says
+    let local: Vec<Suggestion> = changed
+        .iter()
+        .flat_map(|p| {
+            let mut found = state.style_guide.check(&p.text);
+            for s in &mut found {
+                s.offset += p.offset;
+            }
+            found
+        })
+        .filter(|s| !dictionary::contains(&state.config.dictionary, &s.original))
+        .collect();
+    merge_suggestions(state, local);
+
+    if let Some(reason) = paused {
+        // Whatever is still running was for text that has since changed
+        if !state.running_checks.is_empty() {
+            cancel_check(state);
+        }
+        set_status(state, format!("Auto-check paused: {}", reason));
         return;
     }
 
     let request_id = crate::api::next_request_id();
 
-    state.is_checking = true;
-    state.current_check_request_id = Some(request_id);
-    state.status = "Checking...".to_string();
-
-    state.suggestions.clear();
-    state.hovered_suggestion = None;
-    state.last_checked_text = text.clone();
+    // Checks still running count towards the same totals
+    if state.running_checks.is_empty() {
+        state.dropped_matches = 0;
+        state.check_usage = UsageTotals::default();
+    }
+    state.running_checks.push(request_id);
+    set_status(state, "Checking...");
+
+    let language = match state.config.language {
+        LanguageSetting::Fixed(lang) => lang,
+        // Edits rarely change the language; keep the last guess when unsure
+        LanguageSetting::Auto => language::detect(&text)
+            .or(state.checked_language)
+            .unwrap_or(Lang::Eng),
+    };
+    state.checked_language = Some(language);
 
     let request = ApiRequest {
         job: ApiJob::Grammar {
-            text: text.clone(),
-            api_key: state.config.api_key_for_provider(&state.config.provider),
-            model: state.config.model.clone(),
-            provider: state.config.provider.clone(),
+            paragraphs: changed.clone(),
+            backends: state
+                .config
+                .backends()
+                .into_iter()
+                .map(|(endpoint, model)| {
+                    let context_window = listed_model(state, &endpoint.provider, &model)
+                        .and_then(|m| m.context_length);
+                    (endpoint, model, context_window)
+                })
+                .collect(),
             history: state
                 .message_history
                 .get_entries()
                 .into_iter()
                 .cloned()
                 .collect(),
+            network: state.config.network.clone(),
+            options: Box::new(CheckOptions {
+                client: None,
+                stream: state.config.stream_responses,
+                retry: state.config.retry_policy(),
+                cache: result_cache(state).filter(|_| state.config.cache_enabled),
+                fix_invalid_json: state.config.fix_invalid_json,
+                timeout: Duration::from_secs(state.config.check_timeout_secs),
+                context_window: None,
+                prompt: Prompt {
+                    language: language.eng_name().to_string(),
+                    // Only English comes in the varieties offered
+                    dialect: match language {
+                        Lang::Eng => state.config.dialect.prompt_name().to_string(),
+                        _ => String::new(),
+                    },
+                    style_rules: state.style_guide.prompt_rules(),
+                    ..state.config.prompt()
+                },
+                language,
+                dialect: state.config.dialect,
+            }),
         },
         request_id,
     };
 
-    // Store the text for later use in history
-    state.pending_check_text = Some(text);
+    // Store the paragraphs for later use in history
+    state
+        .pending_paragraphs
+        .extend(
+            changed
+                .into_iter()
+                .enumerate()
+                .map(|(index, paragraph)| PendingParagraph {
+                    check: request_id,
+                    index,
+                    paragraph,
+                }),
+        );
 
     if let Err(e) = state.api_sender.send(request) {
-        state.status = format!("Internal error: failed to send request ({})", e);
-        state.is_checking = false;
-        state.current_check_request_id = None;
+        set_error(
+            state,
+            GrammyError::Internal(format!("failed to send request ({})", e)),
+        );
+        finish_check(state, request_id);
+    }
+}
+
+/// Abort every running grammar check; their results would describe stale text.
+fn cancel_check(state: &mut State) {
+    let _ = state.api_sender.send(ApiRequest {
+        job: ApiJob::CancelGrammar,
+        request_id: crate::api::next_request_id(),
+    });
+    state.running_checks.clear();
+    state.pending_paragraphs.clear();
+}
+
+/// Forget a check that has ended, with any of its paragraphs not answered for.
+fn finish_check(state: &mut State, request_id: u64) {
+    state.running_checks.retain(|&id| id != request_id);
+    state.pending_paragraphs.retain(|p| p.check != request_id);
+}
+
+/// Move paragraphs being checked to where their text now is, and abort the
+/// checks of those `edit` touches, leaving them to be sent again. Returns
+/// whether any was aborted.
+fn shift_pending(state: &mut State, edit: TextEdit) -> bool {
+    let mut touched: Vec<(u64, usize)> = Vec::new();
+    state.pending_paragraphs.retain_mut(|p| {
+        match edit.shift(p.paragraph.offset, p.paragraph.text.len()) {
+            Some(offset) => {
+                p.paragraph.offset = offset;
+                true
+            }
+            None => {
+                touched.push((p.check, p.index));
+                false
+            }
+        }
+    });
+
+    let mut checks: Vec<u64> = touched.iter().map(|&(check, _)| check).collect();
+    checks.dedup();
+    for check in checks {
+        let paragraphs = touched
+            .iter()
+            .filter(|&&(c, _)| c == check)
+            .map(|&(_, index)| index)
+            .collect();
+        let _ = state.api_sender.send(ApiRequest {
+            job: ApiJob::CancelParagraphs { check, paragraphs },
+            request_id: crate::api::next_request_id(),
+        });
     }
+    !touched.is_empty()
+}
+
+/// Keep suggestions and checked paragraphs outside `edit`, moved to where
+/// their text now is; anything the edit touches is dropped, and its check
+/// aborted if it is still running.
+fn shift_for_edit(state: &mut State, edit: TextEdit) {
+    shift_pending(state, edit);
+    state
+        .suggestions
+        .retain_mut(|s| match edit.shift(s.offset, s.length) {
+            Some(offset) => {
+                s.offset = offset;
+                true
+            }
+            None => false,
+        });
+    state
+        .checked_paragraphs
+        .retain_mut(|p| match edit.shift(p.offset, p.text.len()) {
+            Some(offset) => {
+                p.offset = offset;
+                true
+            }
+            None => false,
+        });
+
+    if let Some(id) = &state.hovered_suggestion {
+        if !state.suggestions.iter().any(|s| &s.id == id) {
+            state.hovered_suggestion = None;
+        }
+    }
+}
+
+/// Add suggestions not already present; streamed ones arrive again at the
+/// end, and the model may repeat what the style guide check found.
+fn merge_suggestions(state: &mut State, suggestions: Vec<Suggestion>) {
+    for suggestion in suggestions {
+        if !state.suggestions.iter().any(|s| {
+            s.id == suggestion.id || (s.offset, s.length) == (suggestion.offset, suggestion.length)
+        }) {
+            state.suggestions.push(suggestion);
+        }
+    }
+    state.suggestions.sort_by_key(|s| s.offset);
+}
+
+/// The paragraph `index` of check `request_id`, if it is still wanted.
+fn pending_paragraph(state: &State, request_id: u64, index: usize) -> Option<&PendingParagraph> {
+    state
+        .pending_paragraphs
+        .iter()
+        .find(|p| (p.check, p.index) == (request_id, index))
 }
 
 fn process_api_responses(state: &mut State) {
     loop {
         match state.api_receiver.try_recv() {
             Ok(response) => match response {
-                ApiResponse::GrammarSuccess {
+                ApiResponse::GrammarPartial {
+                    paragraph,
                     suggestions,
                     request_id,
                 } => {
-                    if state.current_check_request_id != Some(request_id) {
+                    let Some(pending) = pending_paragraph(state, request_id, paragraph) else {
+                        continue;
+                    };
+
+                    let suggestions = shift_suggestions(suggestions, pending.paragraph.offset);
+                    merge_suggestions(state, suggestions);
+                    set_status(
+                        state,
+                        format!("Checking... {} found", state.suggestions.len()),
+                    );
+                }
+                ApiResponse::GrammarRetrying {
+                    attempt,
+                    max_retries,
+                    delay,
+                    reason,
+                    request_id,
+                } => {
+                    if !state.running_checks.contains(&request_id) {
                         continue;
                     }
 
-                    state.is_checking = false;
-                    state.current_check_request_id = None;
-
-                    // Save to history for cycle prevention
-                    if let Some(user_text) = state.pending_check_text.take() {
-                        // Format LLM response as JSON for history context
-                        let assistant_content = if suggestions.is_empty() {
-                            r#"{"matches":[]}"#.to_string()
-                        } else {
-                            serde_json::to_string(&serde_json::json!({
-                                "matches": suggestions.iter().map(|s| {
-                                    serde_json::json!({
-                                        "message": s.message,
-                                        "original": s.original,
-                                        "replacement": s.replacement,
-                                        "severity": format!("{:?}", s.severity).to_lowercase()
-                                    })
-                                }).collect::<Vec<_>>()
-                            }))
-                            .unwrap_or_else(|_| r#"{"matches":[]}"#.to_string())
-                        };
-                        state
-                            .message_history
-                            .push_pair(format!("Text:\n{}", user_text), assistant_content);
+                    let status = format!(
+                        "{}; retrying in {}s (attempt {}/{})",
+                        reason,
+                        delay.as_secs_f32().ceil(),
+                        attempt,
+                        max_retries
+                    );
+                    set_status(state, status);
+                }
+                ApiResponse::GrammarDropped { count, request_id } => {
+                    if !state.running_checks.contains(&request_id) {
+                        continue;
                     }
 
-                    state.suggestions = suggestions;
-                    if state.suggestions.is_empty() {
-                        state.status = "All good!".to_string();
-                    } else {
-                        state.status = format!("{} suggestion(s)", state.suggestions.len());
+                    state.dropped_matches += count;
+                }
+                ApiResponse::GrammarUsage {
+                    provider,
+                    model,
+                    usage,
+                    request_id,
+                } => {
+                    record_usage(state, &provider, &model, usage, request_id);
+                }
+                ApiResponse::GrammarFallback {
+                    from,
+                    to,
+                    reason,
+                    request_id,
+                } => {
+                    if !state.running_checks.contains(&request_id) {
+                        continue;
                     }
 
-                    if state.pending_recheck {
-                        let delay = state.config.debounce_ms;
-                        if delay <= 5000 {
-                            state.last_edit_time =
-                                Some(Instant::now() - Duration::from_millis(delay));
-                        } else {
-                            state.pending_recheck = false;
-                        }
-                    }
+                    eprintln!("[DEBUG] {} failed ({}), trying {}", from, reason, to);
+                    set_status(state, format!("{} failed; trying {}", from, to));
+                }
+                ApiResponse::GrammarParagraph {
+                    paragraph,
+                    suggestions,
+                    request_id,
+                } => {
+                    let Some(index) = state
+                        .pending_paragraphs
+                        .iter()
+                        .position(|p| (p.check, p.index) == (request_id, paragraph))
+                    else {
+                        continue;
+                    };
+                    let checked = state.pending_paragraphs.remove(index).paragraph;
+
+                    // Save to history for cycle prevention
+                    let found: Vec<&Suggestion> = suggestions.iter().collect();
+                    state
+                        .message_history
+                        .push_pair(format!("Text:\n{}", checked.text), history_response(&found));
+
+                    let suggestions = shift_suggestions(suggestions, checked.offset);
+                    state.checked_paragraphs.push(checked);
+                    merge_suggestions(state, suggestions);
+                    set_status(
+                        state,
+                        format!("Checking... {} found", state.suggestions.len()),
+                    );
                 }
-                ApiResponse::GrammarError {
-                    message,
+                ApiResponse::GrammarSuccess {
+                    sources,
                     request_id,
                 } => {
-                    if state.current_check_request_id != Some(request_id) {
+                    if !state.running_checks.contains(&request_id) {
                         continue;
                     }
 
-                    state.is_checking = false;
-                    state.current_check_request_id = None;
-                    state.status = message;
-
-                    if state.pending_recheck {
-                        let delay = state.config.debounce_ms;
-                        // Only recheck if auto-check is enabled (<= 5000)
-                        if delay <= 5000 {
-                            state.last_edit_time =
-                                Some(Instant::now() - Duration::from_millis(delay));
-                        } else {
-                            state.pending_recheck = false; // Cancel pending recheck if disabled
-                        }
+                    // None answered if every paragraph was edited meanwhile
+                    if !sources.is_empty() {
+                        state.checked_with = sources;
                     }
+                    finish_check(state, request_id);
+                    if !state.running_checks.is_empty() {
+                        continue;
+                    }
+
+                    let mut status = if state.suggestions.is_empty() {
+                        "All good!".to_string()
+                    } else {
+                        format!("{} suggestion(s)", state.suggestions.len())
+                    };
+                    if state.dropped_matches > 0 {
+                        status
+                            .push_str(&format!(" ({} unreadable dropped)", state.dropped_matches));
+                    }
+                    if state.check_usage.requests > 0 {
+                        status.push_str(&format!(" · {}", state.check_usage.summary()));
+                    }
+                    set_status(state, status);
+                }
+                ApiResponse::GrammarError { error, request_id } => {
+                    if !state.running_checks.contains(&request_id) {
+                        continue;
+                    }
+
+                    finish_check(state, request_id);
+                    set_error(state, error);
                 }
                 ApiResponse::TestSuccess { request_id } => {
                     if state.current_test_request_id != Some(request_id) {
@@ -534,42 +1150,51 @@ fn process_api_responses(state: &mut State) {
                     state.is_testing = false;
                     state.current_test_request_id = None;
                     state.test_status = "Connection OK".to_string();
+                    state.test_error = None;
                 }
-                ApiResponse::TestError {
-                    message,
-                    request_id,
-                } => {
+                ApiResponse::TestError { error, request_id } => {
                     if state.current_test_request_id != Some(request_id) {
                         continue;
                     }
 
                     state.is_testing = false;
                     state.current_test_request_id = None;
-                    state.test_status = message;
+                    state.test_status = error.to_string();
+                    state.test_error = Some(error);
                 }
-                ApiResponse::ModelsSuccess { models, provider } => {
-                    match provider {
-                        ApiProvider::OpenAI => state.openai_models = models,
-                        ApiProvider::OpenRouter => state.openrouter_models = models,
-                        ApiProvider::Gemini => state.gemini_models = models,
+                ApiResponse::ModelsSuccess {
+                    models,
+                    provider,
+                    request_id,
+                } => {
+                    if state.current_models_request_ids.get(&provider) != Some(&request_id) {
+                        continue;
                     }
-                    if provider == state.temp_provider {
-                        let models = match state.temp_provider {
-                            ApiProvider::OpenAI => &state.openai_models,
-                            ApiProvider::OpenRouter => &state.openrouter_models,
-                            ApiProvider::Gemini => &state.gemini_models,
-                        };
-                        state.model_combo_state =
-                            iced::widget::combo_box::State::new(models.clone());
+
+                    state.current_models_request_ids.remove(&provider);
+                    let is_current = provider == state.temp_provider;
+                    state.models.insert(provider, models);
+                    model_picker::save(&state.models);
+                    if is_current {
+                        refresh_model_options(state);
                     }
                 }
-                ApiResponse::ModelsError { message } => {
-                    eprintln!("[DEBUG] Failed to fetch models: {}", message);
+                ApiResponse::ModelsError {
+                    error,
+                    provider,
+                    request_id,
+                } => {
+                    if state.current_models_request_ids.get(&provider) != Some(&request_id) {
+                        continue;
+                    }
+
+                    state.current_models_request_ids.remove(&provider);
+                    eprintln!("[DEBUG] Failed to fetch models: {}", error);
                 }
             },
             Err(TryRecvError::Empty) => break,
             Err(TryRecvError::Disconnected) => {
-                state.status = "Internal error: API thread died".to_string();
+                set_error(state, GrammyError::Internal("API thread died".to_string()));
                 break;
             }
         }
@@ -577,37 +1202,23 @@ fn process_api_responses(state: &mut State) {
 }
 
 fn fetch_models_if_needed(state: &mut State) {
-    let api_key = match state.temp_provider {
-        ApiProvider::OpenAI => &state.temp_openai_api_key,
-        ApiProvider::OpenRouter => &state.temp_openrouter_api_key,
-        ApiProvider::Gemini => &state.temp_gemini_api_key,
-    };
+    // Show what we already have while the listing refreshes
+    refresh_model_options(state);
 
-    if api_key.is_empty() {
+    let endpoint = temp_endpoint(state);
+    if endpoint.api_key.is_empty() && endpoint.provider.requires_api_key() {
         return;
     }
 
-    // Check if we already have models for this provider
-    let has_models = match state.temp_provider {
-        ApiProvider::OpenAI => !state.openai_models.is_empty(),
-        ApiProvider::OpenRouter => !state.openrouter_models.is_empty(),
-        ApiProvider::Gemini => !state.gemini_models.is_empty(),
-    };
-
-    if has_models {
-        let models = match state.temp_provider {
-            ApiProvider::OpenAI => &state.openai_models,
-            ApiProvider::OpenRouter => &state.openrouter_models,
-            ApiProvider::Gemini => &state.gemini_models,
-        };
-        state.model_combo_state = iced::widget::combo_box::State::new(models.clone());
-    }
-
     let request_id = crate::api::next_request_id();
+    state
+        .current_models_request_ids
+        .insert(endpoint.provider.clone(), request_id);
     let request = ApiRequest {
         job: ApiJob::FetchModels {
-            api_key: api_key.clone(),
-            provider: state.temp_provider.clone(),
+            endpoint,
+            network: state.temp_network.clone(),
+            timeout: Duration::from_secs(state.config.models_timeout_secs),
         },
         request_id,
     };
@@ -615,6 +1226,121 @@ fn fetch_models_if_needed(state: &mut State) {
     let _ = state.api_sender.send(request);
 }
 
+/// Format suggestions as the JSON the LLM would have answered with.
+fn history_response(suggestions: &[&Suggestion]) -> String {
+    if suggestions.is_empty() {
+        return r#"{"matches":[]}"#.to_string();
+    }
+    serde_json::to_string(&serde_json::json!({
+        "matches": suggestions.iter().map(|s| {
+            serde_json::json!({
+                "message": s.message,
+                "original": s.original,
+                "replacement": s.replacement,
+                "severity": s.severity.name()
+            })
+        }).collect::<Vec<_>>()
+    }))
+    .unwrap_or_else(|_| r#"{"matches":[]}"#.to_string())
+}
+
+/// A budget limit as shown in its settings field; no limit shows as empty.
+fn budget_text(limit: f64) -> String {
+    if limit > 0.0 {
+        limit.to_string()
+    } else {
+        String::new()
+    }
+}
+
+fn parse_budget(text: &str) -> f64 {
+    text.trim()
+        .trim_start_matches('$')
+        .replace(',', "")
+        .parse::<f64>()
+        .ok()
+        .filter(|v| v.is_finite() && *v > 0.0)
+        .unwrap_or(0.0)
+}
+
+/// `model` as it appears in the provider's last listing.
+fn listed_model<'a>(
+    state: &'a State,
+    provider: &ApiProvider,
+    model: &str,
+) -> Option<&'a ModelInfo> {
+    state
+        .models
+        .get(provider)
+        .and_then(|models| models.iter().find(|m| m.id == model))
+}
+
+/// Rebuild the model picker from the current provider's listing and filter.
+fn refresh_model_options(state: &mut State) {
+    let models = state
+        .models
+        .get(&state.temp_provider)
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+    state.model_options = model_picker::options(&state.temp_provider, models, &state.model_filter);
+    state.model_combo_state = iced::widget::combo_box::State::new(state.model_options.clone());
+}
+
+/// Add a request's tokens and estimated cost to every running total. Costs
+/// use the provider's listed price when it publishes one.
+fn record_usage(
+    state: &mut State,
+    provider: &ApiProvider,
+    model: &str,
+    usage: Usage,
+    request_id: u64,
+) {
+    let pricing = listed_model(state, provider, model)
+        .and_then(|m| m.pricing)
+        .or_else(|| usage::list_price(provider, model));
+    let cost = pricing.map(|p| p.cost(&usage));
+
+    if state.running_checks.contains(&request_id) {
+        state.check_usage.record(usage, cost);
+    }
+    state.session_usage.record(usage, cost);
+    state.usage_log.record(usage, cost);
+    state.usage_log.save();
+}
+
+fn set_status(state: &mut State, status: impl Into<String>) {
+    state.status = status.into();
+    state.error = None;
+}
+
+fn set_error(state: &mut State, error: GrammyError) {
+    state.status = error.to_string();
+    state.error = Some(error);
+}
+
+/// Endpoint for the provider currently selected in the settings dialog.
+fn temp_endpoint(state: &State) -> Endpoint {
+    let mut config = state.config.clone();
+    config.custom_base_url = state.temp_custom_base_url.clone();
+    let api_key = state
+        .temp_api_keys
+        .get(&state.temp_provider)
+        .map(|k| k.trim().to_string())
+        .unwrap_or_default();
+    config.set_api_key(&state.temp_provider, api_key);
+    config.endpoint(&state.temp_provider)
+}
+
+fn api_keys(config: &Config) -> HashMap<ApiProvider, String> {
+    ApiProvider::ALL
+        .into_iter()
+        .map(|p| {
+            let key = config.api_key_for_provider(&p);
+            (p, key)
+        })
+        .collect()
+}
+
 fn apply_suggestion(state: &mut State, suggestion_id: &str) {
     let suggestion = state
         .suggestions
@@ -631,14 +1357,14 @@ fn apply_suggestion(state: &mut State, suggestion_id: &str) {
     let end = suggestion.offset + suggestion.length;
 
     if start > text.len() || end > text.len() {
-        state.status = "Invalid suggestion range".to_string();
+        set_status(state, "Invalid suggestion range");
         state.last_edit_time = Some(Instant::now());
         return;
     }
 
     let slice = &text[start..end];
     if slice != suggestion.original {
-        state.status = "Text changed; re-checking...".to_string();
+        set_status(state, "Text changed; re-checking...");
         state.last_edit_time = Some(Instant::now());
         return;
     }
@@ -652,6 +1378,16 @@ fn apply_suggestion(state: &mut State, suggestion_id: &str) {
 
     let delta = replacement.len() as isize - suggestion.length as isize;
 
+    // Paragraphs being checked move too; the one fixed is checked again
+    let edit = TextEdit {
+        start,
+        old_end: end,
+        new_end: start + replacement.len(),
+    };
+    if shift_pending(state, edit) {
+        state.last_edit_time = Some(Instant::now());
+    }
+
     state.suggestions.retain(|s| s.id != suggestion_id);
     for s in &mut state.suggestions {
         if s.offset > suggestion.offset {
@@ -659,12 +1395,21 @@ fn apply_suggestion(state: &mut State, suggestion_id: &str) {
         }
     }
 
+    // Applying a fix does not make its paragraph need another check
+    for p in &mut state.checked_paragraphs {
+        if p.contains(start, suggestion.length) {
+            let end = (p.end() as isize + delta) as usize;
+            p.text = new_text[p.offset..end].to_string();
+        } else if p.offset > start {
+            p.offset = (p.offset as isize + delta) as usize;
+        }
+    }
+
     state.editor = text_editor::Content::with_text(&new_text);
-    state.last_checked_text = new_text;
 
     if state.suggestions.is_empty() {
-        state.status = "All good!".to_string();
+        set_status(state, "All good!");
     } else {
-        state.status = format!("{} suggestion(s)", state.suggestions.len());
+        set_status(state, format!("{} suggestion(s)", state.suggestions.len()));
     }
 }
@@ -11,6 +11,7 @@ pub(super) const COL_SUCCESS: Color = color!(0x10B981); // Emerald
 pub(super) const COL_DANGER: Color = color!(0xEF4444); // Red - Errors
 pub(super) const COL_WARNING: Color = color!(0xF59E0B); // Amber - Warnings
 pub(super) const COL_SUGGESTION: Color = color!(0xFDE047); // Yellow - Suggestions
+pub(super) const COL_VARIANT: Color = color!(0xC084FC); // Violet - Spelling variants
 pub(super) const COL_BORDER: Color = Color {
     r: 1.0,
     g: 1.0,
@@ -1,18 +1,23 @@
 use iced::widget::text::Wrapping;
 use iced::widget::{
-    button, column, container, mouse_area, row, rule, scrollable, slider, text, text_editor,
-    text_input, Column,
+    button, checkbox, column, container, mouse_area, pick_list, row, rule, scrollable, slider,
+    text, text_editor, text_input, Column,
 };
 use iced::{Alignment, Background, Border, Color, Element, Fill, Length, Padding, Theme};
 
-use crate::config::ApiProvider;
+use crate::config::{ApiProvider, DEFAULT_CUSTOM_BASE_URL};
+use crate::dialect::Dialect;
+use crate::dictionary;
+use crate::language::LanguageSetting;
+use crate::prompt::DEFAULT_TEMPLATE_NAME;
 use crate::suggestion::Severity;
+use crate::usage::{format_tokens, BudgetUnit, UsageTotals};
 
 use super::state::{Message, State};
 use super::style::{
     btn_ghost, btn_primary, btn_secondary, btn_success, editor_style, glass_container,
     glass_editor, rule_muted, text_input as style_text_input, COL_BG, COL_DANGER, COL_MUTED,
-    COL_SUCCESS, COL_SUGGESTION, COL_TEXT, COL_WARNING,
+    COL_SUCCESS, COL_SUGGESTION, COL_TEXT, COL_VARIANT, COL_WARNING,
 };
 use super::{highlight, highlight::SuggestionHighlighter};
 
@@ -27,6 +32,7 @@ pub(super) fn view(state: &State) -> Element<'_, Message> {
             .style(|_t| iced::widget::text::Style {
                 color: Some(COL_TEXT),
             }),
+        opened_file(state),
         iced::widget::Space::new().width(Fill),
         button(text("⚙ Settings").size(14))
             .on_press(Message::OpenSettings)
@@ -36,28 +42,46 @@ pub(super) fn view(state: &State) -> Element<'_, Message> {
     .align_y(Alignment::Center)
     .padding(Padding::new(20.0));
 
-    let status_color = if state.status.contains("error") || state.status.contains("Error") {
+    let status_color = if state.error.is_some() {
         COL_DANGER
     } else if state.status == "All good!" {
         COL_SUCCESS
+    } else if state.status.starts_with("Auto-check paused") {
+        COL_WARNING
     } else {
         COL_MUTED
     };
 
+    let error_action: Element<'_, Message> = match &state.error {
+        Some(e) if e.needs_settings() => {
+            error_action_button("Open settings", Message::OpenSettings)
+        }
+        Some(e) if e.is_retryable() => error_action_button("Retry", Message::ForceCheck),
+        _ => iced::widget::Space::new().width(0.0).into(),
+    };
+
     let status_bar = row![
         text(&state.status)
             .size(12)
             .style(move |_t| iced::widget::text::Style {
                 color: Some(status_color),
             }),
+        error_action,
         text(" · ").size(12).style(|_t| iced::widget::text::Style {
             color: Some(COL_MUTED),
         }),
+        checked_language(state),
+        style_guide(state),
         text("Suggestions appear as you type")
             .size(12)
             .style(|_t| iced::widget::text::Style {
                 color: Some(COL_MUTED),
             }),
+        iced::widget::Space::new().width(Fill),
+        button(text(format!("Today: {}", state.usage_log.today().summary())).size(12))
+            .on_press(Message::OpenStats)
+            .padding(Padding::from([4.0, 10.0]))
+            .style(btn_ghost),
     ]
     .align_y(Alignment::Center)
     .padding(Padding::new(12.0));
@@ -88,12 +112,27 @@ pub(super) fn view(state: &State) -> Element<'_, Message> {
             });
 
     if state.show_settings {
-        settings_modal(base.into(), state)
+        modal(base.into(), settings_content(state))
+    } else if state.show_stats {
+        modal(base.into(), stats_content(state))
+    } else if let Some(reason) = &state.confirm_over_budget {
+        modal(base.into(), confirm_over_budget(reason))
     } else {
         base.into()
     }
 }
 
+fn error_action_button(label: &'static str, message: Message) -> Element<'static, Message> {
+    container(
+        button(text(label).size(12))
+            .on_press(message)
+            .padding(Padding::from([4.0, 10.0]))
+            .style(btn_secondary),
+    )
+    .padding(Padding::from([0.0, 8.0]))
+    .into()
+}
+
 fn editor(state: &State) -> Element<'_, Message> {
     let title = text("Your text")
         .size(14)
@@ -129,6 +168,98 @@ fn editor(state: &State) -> Element<'_, Message> {
         .into()
 }
 
+/// Which backends answered the latest check; highlighted when a fallback did.
+fn checked_with(state: &State) -> Element<'_, Message> {
+    if state.checked_with.is_empty() {
+        return iced::widget::Space::new().height(0.0).into();
+    }
+    let fell_back = state.checked_with.iter().any(|s| s.ends_with("(fallback)"));
+    text(format!("Checked with {}", state.checked_with.join(", ")))
+        .size(12)
+        .color(if fell_back { COL_WARNING } else { COL_MUTED })
+        .into()
+}
+
+/// The language the latest check asked for, followed by a separator.
+fn checked_language(state: &State) -> Element<'_, Message> {
+    let Some(lang) = state.checked_language else {
+        return iced::widget::Space::new().width(0.0).into();
+    };
+    let label = match state.config.language {
+        LanguageSetting::Auto => format!("{} (detected) · ", lang.eng_name()),
+        LanguageSetting::Fixed(_) => format!("{} · ", lang.eng_name()),
+    };
+    text(label).size(12).color(COL_MUTED).into()
+}
+
+/// Name of the file being checked, when one was given on the command line.
+fn opened_file(state: &State) -> Element<'_, Message> {
+    let Some(name) = state.opened_file.as_ref().and_then(|f| f.file_name()) else {
+        return iced::widget::Space::new().width(0.0).into();
+    };
+    text(format!("  {}", name.to_string_lossy()))
+        .size(16)
+        .color(COL_MUTED)
+        .into()
+}
+
+/// Where the style guide in effect comes from, or why it is not applied,
+/// followed by a separator.
+fn style_guide(state: &State) -> Element<'_, Message> {
+    if let Some(error) = &state.style_guide_error {
+        return text(format!("Style guide ignored: {} · ", error))
+            .size(12)
+            .color(COL_WARNING)
+            .into();
+    }
+    let Some(path) = &state.style_guide_path else {
+        return iced::widget::Space::new().width(0.0).into();
+    };
+    text(format!("Style guide: {} · ", path.display()))
+        .size(12)
+        .color(COL_MUTED)
+        .into()
+}
+
+/// Which prompt template the next checks use, offered once the config
+/// defines any.
+fn prompt_picker(state: &State) -> Element<'_, Message> {
+    let templates = &state.config.prompt_templates;
+    if templates.is_empty() {
+        return iced::widget::Space::new().width(0.0).into();
+    }
+
+    let names: Vec<String> = std::iter::once(DEFAULT_TEMPLATE_NAME.to_string())
+        .chain(templates.iter().map(|t| t.name.clone()))
+        .collect();
+    let selected = state.config.selected_template().map(|t| t.name.clone());
+    let reset: Element<'_, Message> = if selected.is_some() {
+        button(text("Reset").size(12))
+            .on_press(Message::PromptTemplateSelected(
+                DEFAULT_TEMPLATE_NAME.to_string(),
+            ))
+            .padding(Padding::from([6.0, 10.0]))
+            .style(btn_ghost)
+            .into()
+    } else {
+        iced::widget::Space::new().width(0.0).into()
+    };
+
+    row![
+        pick_list(
+            names,
+            Some(selected.unwrap_or_else(|| DEFAULT_TEMPLATE_NAME.to_string())),
+            Message::PromptTemplateSelected,
+        )
+        .text_size(12)
+        .padding(Padding::from([6.0, 10.0])),
+        reset,
+    ]
+    .spacing(4)
+    .align_y(Alignment::Center)
+    .into()
+}
+
 fn suggestions_sidebar(state: &State) -> Element<'_, Message> {
     let header = column![
         row![
@@ -138,6 +269,7 @@ fn suggestions_sidebar(state: &State) -> Element<'_, Message> {
                     color: Some(COL_TEXT),
                 }),
             iced::widget::Space::new().width(Fill),
+            prompt_picker(state),
             button(text("Check again").size(12))
                 .on_press(Message::ForceCheck)
                 .padding(Padding::from([6.0, 12.0]))
@@ -145,11 +277,13 @@ fn suggestions_sidebar(state: &State) -> Element<'_, Message> {
         ]
         .align_y(Alignment::Center)
         .spacing(10),
+        checked_with(state),
         rule::horizontal(1).style(rule_muted),
     ]
     .spacing(16);
 
-    let body: Element<_> = if state.last_edit_time.is_some() {
+    // Suggestions for untouched paragraphs stay listed while typing
+    let body: Element<_> = if state.last_edit_time.is_some() && state.suggestions.is_empty() {
         container(text("...").size(14).style(|_t| iced::widget::text::Style {
             color: Some(COL_MUTED),
         }))
@@ -157,7 +291,7 @@ fn suggestions_sidebar(state: &State) -> Element<'_, Message> {
         .center_y(Fill)
         .height(Fill)
         .into()
-    } else if state.is_checking {
+    } else if !state.running_checks.is_empty() && state.suggestions.is_empty() {
         container(
             text("Checking...")
                 .size(14)
@@ -210,6 +344,19 @@ fn suggestions_sidebar(state: &State) -> Element<'_, Message> {
         .into()
 }
 
+/// Button to stop flagging the word a suggestion is about; phrases have none.
+fn add_to_dictionary(s: &crate::suggestion::Suggestion) -> Option<Element<'_, Message>> {
+    dictionary::word(&s.original)?;
+    Some(
+        button(text("Add to dictionary").size(12))
+            .on_press(Message::AddToDictionary(s.id.clone()))
+            .padding(Padding::from([8.0, 16.0]))
+            .style(btn_ghost)
+            .width(Fill)
+            .into(),
+    )
+}
+
 fn suggestion_card<'a>(
     s: &'a crate::suggestion::Suggestion,
     hovered: bool,
@@ -219,6 +366,7 @@ fn suggestion_card<'a>(
         Severity::Error => ("Error", COL_DANGER),
         Severity::Warning => ("Warning", COL_WARNING),
         Severity::Suggestion => ("Suggestion", COL_SUGGESTION),
+        Severity::Variant => ("Spelling variant", COL_VARIANT),
     };
 
     let badge = text(severity_label)
@@ -275,7 +423,10 @@ fn suggestion_card<'a>(
         .width(Fill)
         .align_y(Alignment::Center);
 
-        let action_row = row![dismiss, accept].spacing(12);
+        let mut action_row = row![dismiss, accept].spacing(12);
+        if let Some(add) = add_to_dictionary(s) {
+            action_row = action_row.push(add);
+        }
         (row_content, action_row)
     } else {
         // Comment only
@@ -289,7 +440,11 @@ fn suggestion_card<'a>(
             .width(Fill)
             .align_y(Alignment::Center);
 
-        (row_content, row![dismiss])
+        let mut action_row = row![dismiss].spacing(12);
+        if let Some(add) = add_to_dictionary(s) {
+            action_row = action_row.push(add);
+        }
+        (row_content, action_row)
     };
 
     container(
@@ -330,16 +485,14 @@ fn suggestion_card<'a>(
     .into()
 }
 
-fn settings_modal<'a>(base: Element<'a, Message>, state: &'a State) -> Element<'a, Message> {
+fn modal<'a>(base: Element<'a, Message>, content: Element<'a, Message>) -> Element<'a, Message> {
     use iced::widget::stack;
 
-    let content = settings_content(state);
-
     let overlay = container(
         container(content)
             .padding(Padding::new(24.0))
             .style(glass_container)
-            .width(450)
+            .width(560)
             .height(Length::Shrink),
     )
     .width(Fill)
@@ -355,56 +508,90 @@ fn settings_modal<'a>(base: Element<'a, Message>, state: &'a State) -> Element<'
 }
 
 fn settings_content(state: &State) -> Element<'_, Message> {
-    let provider_row = row![
-        provider_button(
-            "OpenAI",
-            state.temp_provider == ApiProvider::OpenAI,
-            Message::SelectProvider(ApiProvider::OpenAI)
-        ),
-        provider_button(
-            "OpenRouter",
-            state.temp_provider == ApiProvider::OpenRouter,
-            Message::SelectProvider(ApiProvider::OpenRouter),
-        ),
-        provider_button(
-            "Gemini",
-            state.temp_provider == ApiProvider::Gemini,
-            Message::SelectProvider(ApiProvider::Gemini),
-        ),
-    ]
-    .spacing(12);
+    let provider_row =
+        ApiProvider::ALL
+            .into_iter()
+            .fold(iced::widget::Row::new().spacing(12), |row, p| {
+                let selected = state.temp_provider == p;
+                row.push(provider_button(
+                    p.name(),
+                    selected,
+                    Message::SelectProvider(p),
+                ))
+            });
 
-    let api_key_input: Element<'_, Message> = match state.temp_provider {
-        ApiProvider::OpenAI => text_input("sk-...", &state.temp_openai_api_key)
-            .secure(!state.show_api_key)
-            .on_input(Message::TempOpenAiKeyChanged)
-            .style(style_text_input)
-            .into(),
-        ApiProvider::OpenRouter => text_input("sk-or-...", &state.temp_openrouter_api_key)
-            .secure(!state.show_api_key)
-            .on_input(Message::TempOpenRouterKeyChanged)
-            .style(style_text_input)
-            .into(),
-        ApiProvider::Gemini => text_input("AIza...", &state.temp_gemini_api_key)
-            .secure(!state.show_api_key)
-            .on_input(Message::TempGeminiKeyChanged)
-            .style(style_text_input)
-            .into(),
+    let api_key_input = text_input(
+        state.temp_provider.key_placeholder(),
+        state
+            .temp_api_keys
+            .get(&state.temp_provider)
+            .map(String::as_str)
+            .unwrap_or_default(),
+    )
+    .secure(!state.show_api_key)
+    .on_input(Message::TempApiKeyChanged)
+    .style(style_text_input);
+
+    let base_url_section: Element<'_, Message> = if state.temp_provider == ApiProvider::Custom {
+        column![
+            text("Base URL")
+                .size(14)
+                .style(|_t| iced::widget::text::Style {
+                    color: Some(COL_TEXT)
+                }),
+            text_input(DEFAULT_CUSTOM_BASE_URL, &state.temp_custom_base_url)
+                .on_input(Message::TempCustomBaseUrlChanged)
+                .style(style_text_input),
+        ]
+        .spacing(16)
+        .into()
+    } else {
+        iced::widget::Space::new().height(0.0).into()
     };
 
+    // A model hidden by the filter still shows as the placeholder
+    let selected_model = state
+        .model_options
+        .iter()
+        .find(|o| o.info.id == state.temp_model);
     let model_input = iced::widget::combo_box(
         &state.model_combo_state,
-        "Select a model...",
         if state.temp_model.is_empty() {
-            None
+            "Select a model..."
         } else {
-            Some(&state.temp_model)
+            &state.temp_model
         },
-        Message::ModelSelected,
+        selected_model,
+        |option| Message::ModelSelected(option.info.id),
     )
     .width(Fill)
     .padding(Padding::new(10.0));
 
+    let listed = state
+        .models
+        .get(&state.temp_provider)
+        .map_or(0, |models| models.len());
+    let model_filter = row![
+        checkbox(state.model_filter.structured_output)
+            .label("Supports JSON schema")
+            .on_toggle(Message::ModelFilterStructuredOutputToggled)
+            .text_size(14),
+        text_input("Max $ per M tokens", &state.model_filter.max_price)
+            .on_input(Message::ModelFilterMaxPriceChanged)
+            .style(style_text_input)
+            .width(Length::Fixed(160.0)),
+        iced::widget::Space::new().width(Fill),
+        text(format!(
+            "{} of {} models",
+            state.model_options.len(),
+            listed
+        ))
+        .size(12)
+        .color(COL_MUTED),
+    ]
+    .spacing(12)
+    .align_y(Alignment::Center);
+
     let test_button = button(text(if state.is_testing {
         "Testing..."
     } else {
@@ -437,15 +624,142 @@ fn settings_content(state: &State) -> Element<'_, Message> {
         text(&state.test_status)
             .size(12)
             .style(|_t| iced::widget::text::Style {
-                color: Some(if state.test_status.contains("OK") {
-                    COL_SUCCESS
-                } else {
+                color: Some(if state.test_error.is_some() {
                     COL_DANGER
+                } else if state.is_testing {
+                    COL_MUTED
+                } else {
+                    COL_SUCCESS
                 }),
             })
             .into()
     };
 
+    let budget_unit = |label: &'static str, unit: BudgetUnit| {
+        container(provider_button(
+            label,
+            state.temp_budget_unit == unit,
+            Message::TempBudgetUnitSelected(unit),
+        ))
+        .width(Length::Fixed(72.0))
+    };
+    let budget_row = row![
+        budget_unit("USD", BudgetUnit::Usd),
+        budget_unit("Tokens", BudgetUnit::Tokens),
+        text_input("Daily: no limit", &state.temp_daily_budget)
+            .on_input(Message::TempDailyBudgetChanged)
+            .style(style_text_input),
+        text_input("Monthly: no limit", &state.temp_monthly_budget)
+            .on_input(Message::TempMonthlyBudgetChanged)
+            .style(style_text_input),
+    ]
+    .spacing(8)
+    .align_y(Alignment::Center);
+
+    let fallback_list = state.temp_fallbacks.iter().enumerate().fold(
+        Column::new().spacing(8),
+        |col, (i, fallback)| {
+            col.push(
+                row![
+                    text(format!(
+                        "{}. {} · {}",
+                        i + 1,
+                        fallback.provider.name(),
+                        fallback.model
+                    ))
+                    .size(14)
+                    .color(COL_TEXT)
+                    .width(Fill),
+                    button(text("Remove").size(12))
+                        .on_press(Message::RemoveFallback(i))
+                        .padding(Padding::from([6.0, 12.0]))
+                        .style(btn_ghost),
+                ]
+                .spacing(8)
+                .align_y(Alignment::Center),
+            )
+        },
+    );
+    let dictionary_list: Element<'_, Message> = if state.temp_dictionary.is_empty() {
+        text("Words added from suggestions are never flagged again.")
+            .size(12)
+            .color(COL_MUTED)
+            .into()
+    } else {
+        state
+            .temp_dictionary
+            .iter()
+            .enumerate()
+            .fold(Column::new().spacing(8), |col, (i, word)| {
+                col.push(
+                    row![
+                        text(word).size(14).color(COL_TEXT).width(Fill),
+                        button(text("Remove").size(12))
+                            .on_press(Message::RemoveDictionaryWord(i))
+                            .padding(Padding::from([6.0, 12.0]))
+                            .style(btn_ghost),
+                    ]
+                    .spacing(8)
+                    .align_y(Alignment::Center),
+                )
+            })
+            .into()
+    };
+    let add_fallback = row![
+        pick_list(
+            &ApiProvider::ALL[..],
+            Some(state.new_fallback_provider.clone()),
+            Message::NewFallbackProviderSelected,
+        )
+        .width(Length::Fixed(130.0)),
+        text_input(
+            state.new_fallback_provider.default_model(),
+            &state.new_fallback_model
+        )
+        .on_input(Message::NewFallbackModelChanged)
+        .on_submit(Message::AddFallback)
+        .style(style_text_input),
+        button(text("Add fallback").size(12))
+            .on_press(Message::AddFallback)
+            .padding(Padding::from([8.0, 12.0]))
+            .style(btn_secondary),
+    ]
+    .spacing(8)
+    .align_y(Alignment::Center);
+
+    let network = &state.temp_network;
+    let proxy_rows = column![
+        row![
+            text_input(
+                "HTTPS proxy: http://proxy:3128 or socks5://host:1080",
+                &network.https_proxy
+            )
+            .on_input(Message::TempHttpsProxyChanged)
+            .style(style_text_input),
+            text_input("HTTP proxy", &network.http_proxy)
+                .on_input(Message::TempHttpProxyChanged)
+                .style(style_text_input),
+        ]
+        .spacing(8),
+        row![
+            text_input("Proxy username", &network.proxy_username)
+                .on_input(Message::TempProxyUsernameChanged)
+                .style(style_text_input),
+            text_input("Proxy password", &network.proxy_password)
+                .secure(true)
+                .on_input(Message::TempProxyPasswordChanged)
+                .style(style_text_input),
+        ]
+        .spacing(8),
+        text_input("No proxy for: localhost, .corp.example", &network.no_proxy)
+            .on_input(Message::TempNoProxyChanged)
+            .style(style_text_input),
+        text_input("Extra CA certificates (PEM file)", &network.ca_cert_file)
+            .on_input(Message::TempCaCertFileChanged)
+            .style(style_text_input),
+    ]
+    .spacing(8);
+
     let buttons = row![
         button(text("Cancel"))
             .on_press(Message::CloseSettings)
@@ -460,49 +774,207 @@ fn settings_content(state: &State) -> Element<'_, Message> {
     .align_y(Alignment::Center)
     .spacing(12);
 
+    // The dialog outgrows small windows
+    scrollable(
+        column![
+            text("Settings")
+                .size(22)
+                .style(|_t| iced::widget::text::Style {
+                    color: Some(COL_TEXT)
+                }),
+            iced::widget::Space::new().height(12.0),
+            text("API Provider")
+                .size(14)
+                .style(|_t| iced::widget::text::Style {
+                    color: Some(COL_TEXT)
+                }),
+            provider_row,
+            base_url_section,
+            text("API Key")
+                .size(14)
+                .style(|_t| iced::widget::text::Style {
+                    color: Some(COL_TEXT)
+                }),
+            row![
+                api_key_input,
+                button(text(if state.show_api_key { "🙈" } else { "👁" }))
+                    .on_press(Message::ToggleShowApiKey)
+                    .padding(Padding::new(10.0))
+                    .style(btn_ghost),
+            ]
+            .spacing(8)
+            .align_y(Alignment::Center),
+            text("Model")
+                .size(14)
+                .style(|_t| iced::widget::text::Style {
+                    color: Some(COL_TEXT)
+                }),
+            model_input,
+            model_filter,
+            text("Fallbacks, tried in order when the model above fails")
+                .size(14)
+                .color(COL_TEXT),
+            fallback_list,
+            add_fallback,
+            iced::widget::Space::new().height(4.0),
+            text("Document language").size(14).color(COL_TEXT),
+            row![
+                pick_list(
+                    LanguageSetting::all(),
+                    Some(state.temp_language),
+                    Message::TempLanguageSelected,
+                )
+                .width(Fill),
+                text("English spelling").size(14).color(COL_MUTED),
+                pick_list(
+                    &Dialect::ALL[..],
+                    Some(state.temp_dialect),
+                    Message::TempDialectSelected,
+                )
+                .width(Length::Fixed(190.0)),
+            ]
+            .spacing(12)
+            .align_y(Alignment::Center),
+            text("Dictionary").size(14).color(COL_TEXT),
+            dictionary_list,
+            text("Auto-check Delay").size(14).color(COL_TEXT),
+            debounce_slider,
+            text("Budget").size(14).color(COL_TEXT),
+            budget_row,
+            checkbox(state.temp_stream_responses)
+                .label("Show suggestions while the model is still writing")
+                .on_toggle(Message::TempStreamResponsesToggled)
+                .text_size(14),
+            row![
+                checkbox(state.temp_cache_enabled)
+                    .label("Reuse results for text already checked")
+                    .on_toggle(Message::TempCacheEnabledToggled)
+                    .text_size(14)
+                    .width(Fill),
+                button(text(format!("Clear cache ({})", format_size(state.cache_size))).size(12))
+                    .on_press_maybe((state.cache_size > 0).then_some(Message::ClearCache))
+                    .padding(Padding::from([6.0, 12.0]))
+                    .style(btn_ghost),
+            ]
+            .spacing(12)
+            .align_y(Alignment::Center),
+            text("Network").size(14).color(COL_TEXT),
+            proxy_rows,
+            iced::widget::Space::new().height(4.0),
+            test_button,
+            test_status,
+            iced::widget::Space::new().height(16.0),
+            buttons,
+        ]
+        .spacing(16)
+        .padding(Padding::ZERO.right(12.0)),
+    )
+    .into()
+}
+
+fn format_size(bytes: u64) -> String {
+    if bytes < 1024 * 1024 {
+        format!("{} KB", bytes.div_ceil(1024))
+    } else {
+        format!("{:.1} MB", bytes as f64 / (1024.0 * 1024.0))
+    }
+}
+
+fn confirm_over_budget(reason: &str) -> Element<'_, Message> {
     column![
-        text("Settings")
+        text("Budget reached")
             .size(22)
             .style(|_t| iced::widget::text::Style {
                 color: Some(COL_TEXT)
             }),
-        iced::widget::Space::new().height(12.0),
-        text("API Provider")
-            .size(14)
-            .style(|_t| iced::widget::text::Style {
-                color: Some(COL_TEXT)
-            }),
-        provider_row,
-        text("API Key")
+        text(reason).size(14).style(|_t| iced::widget::text::Style {
+            color: Some(COL_WARNING),
+        }),
+        text("Automatic checks are paused. Checking now will still be billed.")
             .size(14)
             .style(|_t| iced::widget::text::Style {
-                color: Some(COL_TEXT)
+                color: Some(COL_MUTED),
             }),
         row![
-            api_key_input,
-            button(text(if state.show_api_key { "🙈" } else { "👁" }))
-                .on_press(Message::ToggleShowApiKey)
-                .padding(Padding::new(10.0))
+            button(text("Cancel"))
+                .on_press(Message::CancelForceCheck)
+                .padding(Padding::from([8.0, 16.0]))
                 .style(btn_ghost),
+            iced::widget::Space::new().width(Fill),
+            button(text("Check anyway"))
+                .on_press(Message::ConfirmForceCheck)
+                .padding(Padding::from([8.0, 16.0]))
+                .style(btn_primary),
         ]
-        .spacing(8)
         .align_y(Alignment::Center),
-        text("Model")
-            .size(14)
+    ]
+    .spacing(16)
+    .into()
+}
+
+fn stats_content(state: &State) -> Element<'_, Message> {
+    let cell = |value: String| text(value).size(13).width(Length::FillPortion(2));
+    let header = |label: &'static str| {
+        text(label)
+            .size(12)
+            .width(Length::FillPortion(2))
+            .style(|_t| iced::widget::text::Style {
+                color: Some(COL_MUTED),
+            })
+    };
+
+    let period = |label: &'static str, totals: UsageTotals| {
+        row![
+            text(label).size(13).width(Length::FillPortion(3)),
+            cell(totals.requests.to_string()),
+            cell(format_tokens(totals.prompt_tokens)),
+            cell(format_tokens(totals.completion_tokens)),
+            text(totals.cost_label())
+                .size(13)
+                .width(Length::FillPortion(3)),
+        ]
+        .spacing(8)
+    };
+
+    column![
+        text("Usage")
+            .size(22)
             .style(|_t| iced::widget::text::Style {
                 color: Some(COL_TEXT)
             }),
-        model_input,
-        iced::widget::Space::new().height(4.0),
-        text("Auto-check Delay").size(14).color(COL_TEXT),
-        debounce_slider,
-        iced::widget::Space::new().height(4.0),
-        test_button,
-        test_status,
-        iced::widget::Space::new().height(16.0),
-        buttons,
+        row![
+            iced::widget::Space::new().width(Length::FillPortion(3)),
+            header("Requests"),
+            header("Prompt"),
+            header("Output"),
+            text("Est. cost (USD)")
+                .size(12)
+                .width(Length::FillPortion(3))
+                .style(|_t| iced::widget::text::Style {
+                    color: Some(COL_MUTED),
+                }),
+        ]
+        .spacing(8),
+        rule::horizontal(1).style(rule_muted),
+        period("Last check", state.check_usage),
+        period("This session", state.session_usage),
+        period("Today (UTC)", state.usage_log.today()),
+        period("This month", state.usage_log.this_month()),
+        period("All time", state.usage_log.all_time()),
+        text("Costs are estimates from the provider's listed prices.")
+            .size(12)
+            .style(|_t| iced::widget::text::Style {
+                color: Some(COL_MUTED),
+            }),
+        row![
+            iced::widget::Space::new().width(Fill),
+            button(text("Close"))
+                .on_press(Message::CloseStats)
+                .padding(Padding::from([8.0, 16.0]))
+                .style(btn_secondary),
+        ],
     ]
-    .spacing(16)
+    .spacing(12)
     .into()
 }
 
@@ -513,7 +985,7 @@ fn provider_button(
 ) -> Element<'static, Message> {
     let btn = button(text(label).size(13).align_x(Alignment::Center))
         .on_press(message)
-        .padding(Padding::from([10.0, 16.0]))
+        .padding(Padding::from([10.0, 8.0]))
         .width(Fill)
         .style(move |theme: &Theme, status| {
             if selected {
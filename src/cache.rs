@@ -0,0 +1,290 @@
+//! Content-addressed on-disk cache of grammar check results, so text the
+//! model has already seen (reopened drafts, undo, pasted boilerplate) is not
+//! paid for twice.
+//!
+//! Each entry is a small JSON file named after the digest of its key. Reading
+//! an entry refreshes its modification time, and the least recently used
+//! entries are evicted once the directory outgrows its size limit. New
+//! entries are held in memory until [`CheckCache::flush`], and the size on
+//! disk is kept track of, so the directory is only listed again when the
+//! limit is reached.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::SystemTime;
+
+use crate::suggestion::{LlmMatch, LlmResponse};
+
+/// Everything a cached result depends on. The prompt is hashed in full, so
+/// any change to it invalidates earlier results. The base URL keeps apart
+/// servers of the same provider kind, e.g. two self-hosted ones.
+pub struct CacheKey<'a> {
+    pub provider: &'a str,
+    pub base_url: &'a str,
+    pub model: &'a str,
+    pub prompt: &'a str,
+    pub text: &'a str,
+}
+
+impl CacheKey<'_> {
+    fn digest(&self) -> String {
+        // FNV-1a, 128-bit: stable across Rust versions, unlike `DefaultHasher`
+        const OFFSET: u128 = 0x6c62272e07bb014262b821756295c58d;
+        const PRIME: u128 = 0x0000000001000000000000000000013B;
+
+        let mut hash = OFFSET;
+        for field in [
+            self.provider,
+            self.base_url,
+            self.model,
+            self.prompt,
+            self.text,
+        ] {
+            // A separator byte that never appears in UTF-8 keeps fields apart
+            for byte in field.bytes().chain([0xff]) {
+                hash ^= byte as u128;
+                hash = hash.wrapping_mul(PRIME);
+            }
+        }
+        format!("{:032x}", hash)
+    }
+}
+
+/// Clones share their unwritten entries and the size on disk.
+#[derive(Debug, Clone)]
+pub struct CheckCache {
+    dir: PathBuf,
+    max_bytes: u64,
+    /// Entries put since the last flush, by digest.
+    pending: Arc<Mutex<HashMap<String, String>>>,
+    /// Bytes on disk, once the directory has been listed.
+    known_size: Arc<Mutex<Option<u64>>>,
+}
+
+impl CheckCache {
+    pub fn new(dir: PathBuf, max_bytes: u64) -> Self {
+        Self {
+            dir,
+            max_bytes,
+            pending: Arc::default(),
+            known_size: Arc::default(),
+        }
+    }
+
+    pub fn max_bytes(&self) -> u64 {
+        self.max_bytes
+    }
+
+    /// The cache in the platform's per-user data directory.
+    pub fn in_data_dir(max_bytes: u64) -> Option<Self> {
+        let dirs = directories::ProjectDirs::from("rs", "", "grammy")?;
+        Some(Self::new(dirs.data_dir().join("cache"), max_bytes))
+    }
+
+    pub fn get(&self, key: &CacheKey) -> Option<Vec<LlmMatch>> {
+        let digest = key.digest();
+        if let Some(content) = lock(&self.pending).get(&digest) {
+            let response: LlmResponse = serde_json::from_str(content).ok()?;
+            return Some(response.matches);
+        }
+
+        let path = self.dir.join(digest);
+        let content = fs::read_to_string(&path).ok()?;
+        let response: LlmResponse = serde_json::from_str(&content).ok()?;
+
+        // Mark the entry as recently used
+        if let Ok(file) = fs::File::options().write(true).open(&path) {
+            let _ = file.set_modified(SystemTime::now());
+        }
+
+        Some(response.matches)
+    }
+
+    /// Remember `matches` for `key`; they reach the disk on the next flush.
+    pub fn put(&self, key: &CacheKey, matches: &[LlmMatch]) -> io::Result<()> {
+        let content = serde_json::to_string(&LlmResponse {
+            matches: matches.to_vec(),
+        })
+        .map_err(io::Error::other)?;
+        lock(&self.pending).insert(key.digest(), content);
+        Ok(())
+    }
+
+    /// Write the entries put since the last flush, then evict old ones if
+    /// that took the cache over its limit.
+    pub fn flush(&self) -> io::Result<()> {
+        let pending = std::mem::take(&mut *lock(&self.pending));
+        if pending.is_empty() {
+            return Ok(());
+        }
+        fs::create_dir_all(&self.dir)?;
+
+        let mut known_size = lock(&self.known_size);
+        let mut total = known_size.unwrap_or_else(|| self.size());
+        for (digest, content) in pending {
+            // Write then rename, so a concurrent `get` never sees half an entry
+            let tmp = self.dir.join(format!("{}.tmp", digest));
+            fs::write(&tmp, &content)?;
+            fs::rename(&tmp, self.dir.join(digest))?;
+            // Overwritten entries are counted twice; an early eviction pass recounts
+            total += content.len() as u64;
+        }
+
+        if total > self.max_bytes {
+            total = self.evict()?;
+        }
+        *known_size = Some(total);
+        Ok(())
+    }
+
+    /// Total size of all entries on disk, in bytes.
+    pub fn size(&self) -> u64 {
+        self.entries().iter().map(|(_, len, _)| len).sum()
+    }
+
+    pub fn clear(&self) -> io::Result<()> {
+        lock(&self.pending).clear();
+        *lock(&self.known_size) = None;
+        match fs::remove_dir_all(&self.dir) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    /// Remove the least recently used entries until the cache fits its limit.
+    /// Returns the size left on disk.
+    fn evict(&self) -> io::Result<u64> {
+        let mut entries = self.entries();
+        let mut total: u64 = entries.iter().map(|(_, len, _)| len).sum();
+        if total <= self.max_bytes {
+            return Ok(total);
+        }
+
+        entries.sort_by_key(|(_, _, modified)| *modified);
+        for (path, len, _) in entries {
+            if total <= self.max_bytes {
+                break;
+            }
+            fs::remove_file(path)?;
+            total -= len;
+        }
+        Ok(total)
+    }
+
+    fn entries(&self) -> Vec<(PathBuf, u64, SystemTime)> {
+        let Ok(dir) = fs::read_dir(&self.dir) else {
+            return Vec::new();
+        };
+
+        dir.filter_map(|entry| {
+            let entry = entry.ok()?;
+            let metadata = entry.metadata().ok()?;
+            if !metadata.is_file() {
+                return None;
+            }
+            let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+            Some((entry.path(), metadata.len(), modified))
+        })
+        .collect()
+    }
+}
+
+/// The plain data behind these locks stays usable even if a holder panicked.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::suggestion::Severity;
+    use std::time::Duration;
+
+    fn temp_cache(name: &str, max_bytes: u64) -> CheckCache {
+        let dir =
+            std::env::temp_dir().join(format!("grammy-cache-{}-{}", name, std::process::id()));
+        let cache = CheckCache::new(dir, max_bytes);
+        cache.clear().unwrap();
+        cache
+    }
+
+    fn key<'a>(model: &'a str, text: &'a str) -> CacheKey<'a> {
+        CacheKey {
+            provider: "OpenAI",
+            base_url: "https://api.openai.com/v1",
+            model,
+            prompt: "prompt",
+            text,
+        }
+    }
+
+    fn matches() -> Vec<LlmMatch> {
+        vec![LlmMatch {
+            message: "Subject-verb agreement".to_string(),
+            original: "has".to_string(),
+            replacement: Some("have".to_string()),
+            severity: Severity::Error,
+        }]
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let cache = temp_cache("round-trip", 1 << 20);
+        assert!(cache.get(&key("gpt-4o-mini", "I has a cat.")).is_none());
+
+        cache
+            .put(&key("gpt-4o-mini", "I has a cat."), &matches())
+            .unwrap();
+        // Found before and after it is written out
+        let hit = cache.get(&key("gpt-4o-mini", "I has a cat.")).unwrap();
+        assert_eq!(hit.len(), 1);
+        assert_eq!(cache.size(), 0);
+        cache.flush().unwrap();
+        assert!(cache.size() > 0);
+        let hit = cache.get(&key("gpt-4o-mini", "I has a cat.")).unwrap();
+        assert_eq!(hit.len(), 1);
+        assert_eq!(hit[0].replacement.as_deref(), Some("have"));
+
+        // Every part of the key matters
+        assert!(cache.get(&key("gpt-4o", "I has a cat.")).is_none());
+        assert!(cache
+            .get(&CacheKey {
+                base_url: "http://localhost:11434/v1",
+                ..key("gpt-4o-mini", "I has a cat.")
+            })
+            .is_none());
+        assert!(cache.get(&key("gpt-4o-mini", "I has a dog.")).is_none());
+
+        cache.clear().unwrap();
+        assert_eq!(cache.size(), 0);
+    }
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let cache = temp_cache("evict", 1 << 20);
+        cache.put(&key("m", "old"), &matches()).unwrap();
+        cache.put(&key("m", "new"), &matches()).unwrap();
+        cache.flush().unwrap();
+
+        let old = cache.dir.join(key("m", "old").digest());
+        let past = SystemTime::now() - Duration::from_secs(60);
+        fs::File::options()
+            .write(true)
+            .open(&old)
+            .unwrap()
+            .set_modified(past)
+            .unwrap();
+
+        // Shrink the limit so only one entry fits
+        let entry_size = cache.size() / 2;
+        let cache = CheckCache::new(cache.dir.clone(), entry_size);
+        cache.evict().unwrap();
+
+        assert!(cache.get(&key("m", "old")).is_none());
+        assert!(cache.get(&key("m", "new")).is_some());
+        cache.clear().unwrap();
+    }
+}
@@ -0,0 +1,259 @@
+//! Keeping requests inside the model's context window: rough token counts,
+//! splitting oversized text at natural boundaries, and dropping history that
+//! no longer fits.
+
+use crate::app::history::HistoryEntry;
+use crate::config::ApiProvider;
+
+/// Tokenizers differ per model, so estimates assume about three bytes per
+/// token. That overcounts English (closer to four) and roughly matches CJK
+/// text, which erring on the safe side is meant to cover.
+const BYTES_PER_TOKEN: usize = 3;
+
+/// Role markers and separators each message adds on top of its text.
+const MESSAGE_OVERHEAD_TOKENS: u64 = 8;
+
+/// Context window assumed for models that are neither listed with one nor
+/// in [`CONTEXT_WINDOWS`], e.g. self-hosted ones.
+pub const DEFAULT_CONTEXT_WINDOW: u64 = 8_192;
+
+/// Largest piece of text sent in one request to a model whose context window
+/// is unknown, since [`DEFAULT_CONTEXT_WINDOW`] is only a guess.
+pub const MAX_CHUNK_TOKENS: u64 = 4_000;
+
+/// Longest answer most models give, for providers that don't say otherwise.
+pub const DEFAULT_ANSWER_TOKENS: u64 = 8_192;
+
+/// Tokens of text one token of answer is planned for. Even text with a
+/// mistake every few words gets an answer of about half its size.
+const TEXT_TOKENS_PER_ANSWER_TOKEN: u64 = 2;
+
+/// Text smaller than this is never split further, whatever the window.
+const MIN_CHUNK_TOKENS: u64 = 256;
+
+/// Context windows in tokens of models whose listing does not say, matched
+/// by prefix like the list prices in [`crate::usage`].
+const CONTEXT_WINDOWS: &[(ApiProvider, &str, u64)] = &[
+    (ApiProvider::OpenAI, "gpt-4o", 128_000),
+    (ApiProvider::OpenAI, "gpt-4.1", 1_047_576),
+    (ApiProvider::OpenAI, "gpt-5", 400_000),
+    (ApiProvider::OpenAI, "o3", 200_000),
+    (ApiProvider::OpenAI, "o4-mini", 200_000),
+    (ApiProvider::Gemini, "gemini-2.0", 1_048_576),
+    (ApiProvider::Gemini, "gemini-2.5", 1_048_576),
+    (ApiProvider::Anthropic, "claude-", 200_000),
+];
+
+/// Rough number of tokens `text` takes up.
+pub fn estimate_tokens(text: &str) -> u64 {
+    text.len().div_ceil(BYTES_PER_TOKEN) as u64
+}
+
+/// Built-in context window for `model`, if it is a known one.
+pub fn context_window(provider: &ApiProvider, model: &str) -> Option<u64> {
+    CONTEXT_WINDOWS
+        .iter()
+        .filter(|(p, prefix, _)| p == provider && model.starts_with(prefix))
+        .max_by_key(|(_, prefix, _)| prefix.len())
+        .map(|(_, _, tokens)| *tokens)
+}
+
+/// Tokens the prompt may take in a window of `window` tokens; the rest is
+/// left for the answer.
+pub fn prompt_budget(window: u64) -> u64 {
+    window / 4 * 3
+}
+
+/// Most tokens of text to send in one request next to `system_prompt`: the
+/// window, if known, less the answer's share and the prompt, and no more than
+/// an answer of `max_answer` tokens can cover. Unknown windows are assumed
+/// small and pieces kept to [`MAX_CHUNK_TOKENS`]. History gets whatever the
+/// text leaves; see [`trim_history`].
+pub fn chunk_limit(window: Option<u64>, system_prompt: &str, max_answer: u64) -> u64 {
+    let limit = prompt_budget(window.unwrap_or(DEFAULT_CONTEXT_WINDOW))
+        .saturating_sub(estimate_tokens(system_prompt) + 2 * MESSAGE_OVERHEAD_TOKENS)
+        .min(max_answer.saturating_mul(TEXT_TOKENS_PER_ANSWER_TOKEN))
+        .max(MIN_CHUNK_TOKENS);
+    match window {
+        Some(_) => limit,
+        None => limit.min(MAX_CHUNK_TOKENS),
+    }
+}
+
+/// Split `text` into pieces of at most `max_tokens` estimated tokens, each
+/// with its byte offset in `text`. Cuts go after a paragraph break where
+/// possible, then after a sentence, then between words. Concatenated, the
+/// pieces are `text` again.
+pub fn split(text: &str, max_tokens: u64) -> Vec<(usize, &str)> {
+    let max_bytes = (max_tokens as usize).saturating_mul(BYTES_PER_TOKEN).max(1);
+    let mut chunks = Vec::new();
+    let mut start = 0;
+
+    while text.len() - start > max_bytes {
+        let rest = &text[start..];
+        let window = &rest[..rest.floor_char_boundary(max_bytes)];
+        let cut = match best_cut(window) {
+            Some(cut) => cut,
+            // A single character longer than the limit still has to go somewhere
+            None => rest.chars().next().map_or(rest.len(), char::len_utf8),
+        };
+        chunks.push((start, &rest[..cut]));
+        start += cut;
+    }
+    if start < text.len() || chunks.is_empty() {
+        chunks.push((start, &text[start..]));
+    }
+    chunks
+}
+
+/// Where to end a chunk taken from the start of `window`: the strongest
+/// boundary in its second half, or failing that, anywhere.
+fn best_cut(window: &str) -> Option<usize> {
+    let finders: [fn(&str) -> Option<usize>; 4] =
+        [after_paragraph, after_line, after_sentence, after_word];
+    finders
+        .iter()
+        .find_map(|find| find(window).filter(|&cut| cut >= window.len() / 2))
+        .or_else(|| finders.iter().find_map(|find| find(window)))
+        .filter(|&cut| cut > 0)
+}
+
+fn after_paragraph(window: &str) -> Option<usize> {
+    window.rfind("\n\n").map(|i| i + 2)
+}
+
+fn after_line(window: &str) -> Option<usize> {
+    window.rfind('\n').map(|i| i + 1)
+}
+
+/// After the whitespace following the last `.`, `!` or `?`.
+fn after_sentence(window: &str) -> Option<usize> {
+    let mut following_space = None;
+    for (i, c) in window.char_indices().rev() {
+        if matches!(c, '.' | '!' | '?' | '。' | '！' | '？') {
+            if let Some(end) = following_space {
+                return Some(end);
+            }
+        }
+        following_space = c.is_whitespace().then(|| i + c.len_utf8());
+    }
+    None
+}
+
+fn after_word(window: &str) -> Option<usize> {
+    window
+        .char_indices()
+        .rev()
+        .find(|(_, c)| c.is_whitespace())
+        .map(|(i, c)| i + c.len_utf8())
+}
+
+/// Drop the oldest user/assistant pairs until `history` fits in `max_tokens`.
+pub fn trim_history(mut history: Vec<HistoryEntry>, max_tokens: u64) -> Vec<HistoryEntry> {
+    let tokens = |entries: &[HistoryEntry]| -> u64 {
+        entries
+            .iter()
+            .map(|e| estimate_tokens(&e.content) + MESSAGE_OVERHEAD_TOKENS)
+            .sum()
+    };
+    while !history.is_empty() && tokens(&history) > max_tokens {
+        history.drain(..history.len().min(2));
+    }
+    history
+}
+
+/// Estimated tokens of a request with these parts, including message overhead.
+pub fn request_tokens(system_prompt: &str, history: &[HistoryEntry], user_message: &str) -> u64 {
+    let history: u64 = history
+        .iter()
+        .map(|e| estimate_tokens(&e.content) + MESSAGE_OVERHEAD_TOKENS)
+        .sum();
+    estimate_tokens(system_prompt)
+        + estimate_tokens(user_message)
+        + history
+        + 2 * MESSAGE_OVERHEAD_TOKENS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn joined(chunks: &[(usize, &str)]) -> String {
+        chunks.iter().map(|(_, c)| *c).collect()
+    }
+
+    #[test]
+    fn test_short_text_is_one_chunk() {
+        assert_eq!(split("Hello there.", 100), vec![(0, "Hello there.")]);
+        assert_eq!(split("", 100), vec![(0, "")]);
+    }
+
+    #[test]
+    fn test_splits_at_paragraphs_then_sentences() {
+        let text = "First paragraph here.\n\nSecond one, a bit longer. It has two sentences.";
+        let chunks = split(text, 10);
+        assert_eq!(joined(&chunks), text);
+        assert_eq!(chunks[0], (0, "First paragraph here.\n\n"));
+        assert_eq!(chunks[1], (23, "Second one, a bit longer. "));
+        for (offset, chunk) in &chunks {
+            assert_eq!(&text[*offset..*offset + chunk.len()], *chunk);
+            assert!(estimate_tokens(chunk) <= 10);
+        }
+    }
+
+    #[test]
+    fn test_split_is_safe_for_non_ascii_text() {
+        let text = "Ünïcödé wörds everywhere. ".repeat(20) + &"猫".repeat(50);
+        let chunks = split(&text, 7);
+        assert_eq!(joined(&chunks), text);
+        assert!(chunks.iter().all(|(_, c)| !c.is_empty()));
+
+        // Even a limit below one character makes progress
+        assert_eq!(split("日本", 0).len(), 2);
+    }
+
+    #[test]
+    fn test_trim_history_drops_oldest_pairs() {
+        let entry = |role: &str, content: &str| HistoryEntry {
+            role: role.to_string(),
+            content: content.to_string(),
+        };
+        let history = vec![
+            entry("user", &"old ".repeat(100)),
+            entry("assistant", "{}"),
+            entry("user", "new"),
+            entry("assistant", "{}"),
+        ];
+        assert_eq!(trim_history(history.clone(), 1_000).len(), 4);
+        let trimmed = trim_history(history.clone(), 30);
+        assert_eq!(trimmed.len(), 2);
+        assert_eq!(trimmed[0].content, "new");
+        assert!(trim_history(history, 5).is_empty());
+    }
+
+    #[test]
+    fn test_context_windows() {
+        assert_eq!(
+            context_window(&ApiProvider::OpenAI, "gpt-4o-mini"),
+            Some(128_000)
+        );
+        assert_eq!(
+            context_window(&ApiProvider::Anthropic, "claude-haiku-4-5"),
+            Some(200_000)
+        );
+        assert_eq!(context_window(&ApiProvider::Custom, "llama3"), None);
+
+        // Known windows are filled, less the answer's share and the prompt
+        let prompt = "prompt";
+        let unlimited = u64::MAX;
+        assert_eq!(
+            chunk_limit(Some(128_000), prompt, unlimited),
+            96_000 - estimate_tokens(prompt) - 2 * MESSAGE_OVERHEAD_TOKENS
+        );
+        // ...as far as the answer to them can be complete
+        assert_eq!(chunk_limit(Some(200_000), prompt, 4_096), 8_192);
+        // Unknown ones are capped; tiny ones keep a usable minimum
+        assert_eq!(chunk_limit(None, prompt, unlimited), MAX_CHUNK_TOKENS);
+        assert_eq!(chunk_limit(Some(100), prompt, unlimited), MIN_CHUNK_TOKENS);
+    }
+}
@@ -1,19 +1,48 @@
+use std::fmt;
+use std::time::Duration;
+
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+use crate::cache::CheckCache;
+use crate::dialect::Dialect;
+use crate::language::LanguageSetting;
+use crate::network::NetworkSettings;
+use crate::prompt::{Prompt, PromptTemplate, DEFAULT_TEMPLATE};
+use crate::provider::Endpoint;
+use crate::retry::RetryPolicy;
+use crate::usage::{Budget, BudgetUnit};
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash, Default)]
 pub enum ApiProvider {
     OpenAI,
     #[default]
     OpenRouter,
     Gemini,
+    Anthropic,
+    /// Any self-hosted server speaking the OpenAI `/v1/chat/completions` API
+    /// (llama.cpp, vLLM, Ollama, LM Studio, ...).
+    Custom,
 }
 
+pub const DEFAULT_CUSTOM_BASE_URL: &str = "http://localhost:8080/v1";
+
 impl ApiProvider {
+    pub const ALL: [ApiProvider; 5] = [
+        ApiProvider::OpenAI,
+        ApiProvider::OpenRouter,
+        ApiProvider::Gemini,
+        ApiProvider::Anthropic,
+        ApiProvider::Custom,
+    ];
+
+    /// API root that endpoint paths (`/chat/completions`, `/models`, ...) are appended to.
     pub fn base_url(&self) -> &'static str {
         match self {
-            ApiProvider::OpenAI => "https://api.openai.com/v1/chat/completions",
-            ApiProvider::OpenRouter => "https://openrouter.ai/api/v1/chat/completions",
-            ApiProvider::Gemini => "https://generativelanguage.googleapis.com/v1beta/models/",
+            ApiProvider::OpenAI => "https://api.openai.com/v1",
+            ApiProvider::OpenRouter => "https://openrouter.ai/api/v1",
+            ApiProvider::Gemini => "https://generativelanguage.googleapis.com/v1beta",
+            ApiProvider::Anthropic => "https://api.anthropic.com/v1",
+            ApiProvider::Custom => DEFAULT_CUSTOM_BASE_URL,
         }
     }
 
@@ -22,6 +51,8 @@ impl ApiProvider {
             ApiProvider::OpenAI => "OpenAI",
             ApiProvider::OpenRouter => "OpenRouter",
             ApiProvider::Gemini => "Gemini",
+            ApiProvider::Anthropic => "Anthropic",
+            ApiProvider::Custom => "Custom",
         }
     }
 
@@ -30,8 +61,39 @@ impl ApiProvider {
             ApiProvider::OpenAI => "gpt-4o-mini",
             ApiProvider::OpenRouter => "google/gemini-3-flash-preview",
             ApiProvider::Gemini => "gemini-2.0-flash-exp",
+            ApiProvider::Anthropic => "claude-haiku-4-5",
+            // Local servers name models however they like; the user picks one.
+            ApiProvider::Custom => "",
         }
     }
+
+    /// Self-hosted servers often run without authentication.
+    pub fn requires_api_key(&self) -> bool {
+        !matches!(self, ApiProvider::Custom)
+    }
+
+    pub fn key_placeholder(&self) -> &'static str {
+        match self {
+            ApiProvider::OpenAI => "sk-...",
+            ApiProvider::OpenRouter => "sk-or-...",
+            ApiProvider::Gemini => "AIza...",
+            ApiProvider::Anthropic => "sk-ant-...",
+            ApiProvider::Custom => "Optional",
+        }
+    }
+}
+
+impl fmt::Display for ApiProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// A provider and model to check with when the ones before it fail.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Fallback {
+    pub provider: ApiProvider,
+    pub model: String,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -42,29 +104,147 @@ pub struct Config {
     pub openrouter_api_key: String,
     #[serde(default)]
     pub gemini_api_key: String,
+    #[serde(default)]
+    pub anthropic_api_key: String,
+    #[serde(default)]
+    pub custom_api_key: String,
+    #[serde(default = "default_custom_base_url")]
+    pub custom_base_url: String,
     #[serde(default, rename = "api_key")]
     pub legacy_api_key: Option<String>,
     pub model: String,
     #[serde(default)]
     pub provider: ApiProvider,
+    /// Tried in order when `provider` and `model` are down, rate limited or
+    /// answer with unreadable JSON.
+    #[serde(default)]
+    pub fallbacks: Vec<Fallback>,
+    /// Language the text is checked as; detected per check when `Auto`.
+    #[serde(default)]
+    pub language: LanguageSetting,
+    /// Preferred variety of English; other spellings are flagged as variants.
+    #[serde(default)]
+    pub dialect: Dialect,
+    /// Name of the template in `prompt_templates` to check with; empty or
+    /// unknown names use the built-in prompt.
+    #[serde(default)]
+    pub prompt_template: String,
+    #[serde(default)]
+    pub prompt_templates: Vec<PromptTemplate>,
+    /// Personal dictionary of words never to flag, kept sorted.
+    #[serde(default)]
+    pub dictionary: Vec<String>,
     #[serde(default = "default_debounce")]
     pub debounce_ms: u64,
+    /// Show suggestions while the model is still generating.
+    #[serde(default = "default_true")]
+    pub stream_responses: bool,
+    /// Retries for rate-limited or failed checks; 0 disables retrying.
+    #[serde(default = "default_max_retries")]
+    pub max_retries: u32,
+    #[serde(default = "default_retry_base_delay")]
+    pub retry_base_delay_ms: u64,
+    #[serde(default = "default_retry_max_delay")]
+    pub retry_max_delay_ms: u64,
+    /// Ask the model to resend an answer that could not be read as JSON.
+    #[serde(default = "default_true")]
+    pub fix_invalid_json: bool,
+    /// Reuse earlier results for text that was already checked.
+    #[serde(default = "default_true")]
+    pub cache_enabled: bool,
+    #[serde(default = "default_cache_max_mb")]
+    pub cache_max_mb: u64,
+    /// Seconds one grammar check request may take, including a streamed answer.
+    #[serde(default = "default_check_timeout")]
+    pub check_timeout_secs: u64,
+    /// Seconds each request of a connection test may take.
+    #[serde(default = "default_settings_timeout")]
+    pub test_timeout_secs: u64,
+    /// Seconds the model listing may take.
+    #[serde(default = "default_settings_timeout")]
+    pub models_timeout_secs: u64,
+    /// Unit of `daily_budget` and `monthly_budget`.
+    #[serde(default)]
+    pub budget_unit: BudgetUnit,
+    /// Pause automatic checks once reached; 0 means no limit.
+    #[serde(default)]
+    pub daily_budget: f64,
+    #[serde(default)]
+    pub monthly_budget: f64,
+    /// Proxy and certificate settings, stored as top-level keys.
+    #[serde(flatten)]
+    pub network: NetworkSettings,
 }
 
 fn default_debounce() -> u64 {
     3000
 }
 
+fn default_true() -> bool {
+    true
+}
+
+fn default_max_retries() -> u32 {
+    3
+}
+
+fn default_retry_base_delay() -> u64 {
+    1000
+}
+
+fn default_retry_max_delay() -> u64 {
+    30_000
+}
+
+fn default_cache_max_mb() -> u64 {
+    50
+}
+
+fn default_check_timeout() -> u64 {
+    60
+}
+
+fn default_settings_timeout() -> u64 {
+    20
+}
+
+fn default_custom_base_url() -> String {
+    DEFAULT_CUSTOM_BASE_URL.to_string()
+}
+
 impl Default for Config {
     fn default() -> Self {
         Self {
             openai_api_key: String::new(),
             openrouter_api_key: String::new(),
             gemini_api_key: String::new(),
+            anthropic_api_key: String::new(),
+            custom_api_key: String::new(),
+            custom_base_url: default_custom_base_url(),
             legacy_api_key: None,
             model: "google/gemini-3-flash-preview".to_string(),
             provider: ApiProvider::OpenRouter,
+            fallbacks: Vec::new(),
+            language: LanguageSetting::Auto,
+            dialect: Dialect::Any,
+            prompt_template: String::new(),
+            prompt_templates: Vec::new(),
+            dictionary: Vec::new(),
             debounce_ms: 3000,
+            stream_responses: true,
+            max_retries: default_max_retries(),
+            retry_base_delay_ms: default_retry_base_delay(),
+            retry_max_delay_ms: default_retry_max_delay(),
+            fix_invalid_json: true,
+            cache_enabled: true,
+            cache_max_mb: default_cache_max_mb(),
+            check_timeout_secs: default_check_timeout(),
+            test_timeout_secs: default_settings_timeout(),
+            models_timeout_secs: default_settings_timeout(),
+            budget_unit: BudgetUnit::default(),
+            daily_budget: 0.0,
+            monthly_budget: 0.0,
+            network: NetworkSettings::default(),
         }
     }
 }
@@ -82,6 +262,10 @@ impl Config {
             }
         }
 
+        // The file may have been edited by hand
+        cfg.dictionary.sort();
+        cfg.dictionary.dedup();
+
         cfg
     }
 
@@ -94,6 +278,89 @@ impl Config {
             ApiProvider::OpenAI => self.openai_api_key.clone(),
             ApiProvider::OpenRouter => self.openrouter_api_key.clone(),
             ApiProvider::Gemini => self.gemini_api_key.clone(),
+            ApiProvider::Anthropic => self.anthropic_api_key.clone(),
+            ApiProvider::Custom => self.custom_api_key.clone(),
+        }
+    }
+
+    pub fn set_api_key(&mut self, provider: &ApiProvider, key: String) {
+        let slot = match provider {
+            ApiProvider::OpenAI => &mut self.openai_api_key,
+            ApiProvider::OpenRouter => &mut self.openrouter_api_key,
+            ApiProvider::Gemini => &mut self.gemini_api_key,
+            ApiProvider::Anthropic => &mut self.anthropic_api_key,
+            ApiProvider::Custom => &mut self.custom_api_key,
+        };
+        *slot = key;
+    }
+
+    pub fn base_url_for_provider(&self, provider: &ApiProvider) -> String {
+        match provider {
+            ApiProvider::Custom if !self.custom_base_url.trim().is_empty() => self
+                .custom_base_url
+                .trim()
+                .trim_end_matches('/')
+                .to_string(),
+            _ => provider.base_url().to_string(),
         }
     }
+
+    pub fn retry_policy(&self) -> RetryPolicy {
+        RetryPolicy {
+            max_retries: self.max_retries,
+            base_delay: Duration::from_millis(self.retry_base_delay_ms),
+            max_delay: Duration::from_millis(self.retry_max_delay_ms),
+        }
+    }
+
+    pub fn budget(&self) -> Budget {
+        Budget {
+            unit: self.budget_unit,
+            daily: self.daily_budget,
+            monthly: self.monthly_budget,
+        }
+    }
+
+    /// The result cache, whether or not `cache_enabled` is set.
+    pub fn cache(&self) -> Option<CheckCache> {
+        CheckCache::in_data_dir(self.cache_max_mb.saturating_mul(1024 * 1024))
+    }
+
+    /// The selected template from `prompt_templates`, if any.
+    pub fn selected_template(&self) -> Option<&PromptTemplate> {
+        self.prompt_templates
+            .iter()
+            .find(|t| t.name == self.prompt_template)
+    }
+
+    /// The prompt to check with: the selected template, or the built-in one.
+    pub fn prompt(&self) -> Prompt {
+        let template = self
+            .selected_template()
+            .map_or(DEFAULT_TEMPLATE, |t| t.text.as_str());
+        Prompt {
+            template: template.to_string(),
+            dictionary: self.dictionary.clone(),
+            ..Prompt::default()
+        }
+    }
+
+    /// The configured provider and model followed by the fallbacks.
+    pub fn backends(&self) -> Vec<(Endpoint, String)> {
+        let fallbacks = self
+            .fallbacks
+            .iter()
+            .map(|f| (self.endpoint(&f.provider), f.model.clone()));
+        std::iter::once((self.endpoint(&self.provider), self.model.clone()))
+            .chain(fallbacks)
+            .collect()
+    }
+
+    pub fn endpoint(&self, provider: &ApiProvider) -> Endpoint {
+        Endpoint::new(
+            provider.clone(),
+            self.api_key_for_provider(provider),
+            self.base_url_for_provider(provider),
+        )
+    }
 }
@@ -0,0 +1,286 @@
+//! Regional varieties of English: the preferred one, and telling spelling
+//! variants (color/colour) apart from real misspellings.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Dialect {
+    /// Accept any variety, as long as the text is consistent.
+    #[default]
+    Any,
+    #[serde(rename = "us")]
+    American,
+    #[serde(rename = "uk")]
+    British,
+    Canadian,
+    Australian,
+}
+
+impl Dialect {
+    pub const ALL: [Dialect; 5] = [
+        Dialect::Any,
+        Dialect::American,
+        Dialect::British,
+        Dialect::Canadian,
+        Dialect::Australian,
+    ];
+
+    /// How the prompt names the variety, with a few telltale spellings so
+    /// the model knows what is meant; empty for no preference.
+    pub fn prompt_name(&self) -> &'static str {
+        match self {
+            Dialect::Any => "",
+            Dialect::American => "American English (color, organize, center, traveled)",
+            Dialect::British => "British English (colour, organise, centre, travelled)",
+            Dialect::Canadian => "Canadian English (colour, organize, centre, travelled)",
+            Dialect::Australian => "Australian English (colour, organise, centre, travelled)",
+        }
+    }
+}
+
+impl fmt::Display for Dialect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Dialect::Any => "No preference",
+            Dialect::American => "US English",
+            Dialect::British => "UK English",
+            Dialect::Canadian => "Canadian English",
+            Dialect::Australian => "Australian English",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Whole words spelled differently on either side of the Atlantic.
+const VARIANT_WORDS: &[(&str, &str)] = &[
+    ("grey", "gray"),
+    ("programme", "program"),
+    ("cheque", "check"),
+    ("tyre", "tire"),
+    ("aluminium", "aluminum"),
+    ("plough", "plow"),
+    ("mould", "mold"),
+    ("defence", "defense"),
+    ("offence", "offense"),
+    ("licence", "license"),
+    ("pretence", "pretense"),
+    ("kerb", "curb"),
+];
+
+/// Words whose British `our` is an American `or`, as in colour/color. Other
+/// words with `our` (devour, resource, four) are the same everywhere.
+const OUR_STEMS: &[&str] = &[
+    "ardour",
+    "armour",
+    "behaviour",
+    "candour",
+    "clamour",
+    "colour",
+    "endeavour",
+    "favour",
+    "fervour",
+    "flavour",
+    "harbour",
+    "honour",
+    "humour",
+    "labour",
+    "neighbour",
+    "odour",
+    "parlour",
+    "rigour",
+    "rumour",
+    "saviour",
+    "savour",
+    "splendour",
+    "tumour",
+    "valour",
+    "vapour",
+    "vigour",
+];
+
+/// British `-ise`/`-yse` endings with their `-ize`/`-yze` counterparts.
+/// Canadian English follows the American side for these.
+const Z_ENDINGS: &[(&str, &str)] = &[
+    ("isation", "ization"),
+    ("isations", "izations"),
+    ("ise", "ize"),
+    ("ised", "ized"),
+    ("ises", "izes"),
+    ("ising", "izing"),
+    ("yse", "yze"),
+    ("ysed", "yzed"),
+    ("ysing", "yzing"),
+];
+
+/// Other British endings with their American counterparts.
+const VARIANT_ENDINGS: &[(&str, &str)] = &[
+    ("tre", "ter"),
+    ("tres", "ters"),
+    ("ogue", "og"),
+    ("ogues", "ogs"),
+    ("elled", "eled"),
+    ("elling", "eling"),
+    ("eller", "eler"),
+];
+
+/// Words where a doubled `l` is the same in every variety.
+const INVARIANT_STEMS: &[&str] = &[
+    "compel", "expel", "propel", "repel", "dispel", "excel", "rebel",
+];
+
+/// Whether replacing `original` with `replacement` only changes spellings
+/// between regional varieties, like `colour` to `color`. Case, punctuation and
+/// unchanged words are ignored; every changed word must be such a variant.
+pub fn is_spelling_variant(original: &str, replacement: &str) -> bool {
+    changed_words(original, replacement).is_some_and(|pairs| {
+        !pairs.is_empty() && pairs.iter().all(|(a, b)| americanize(a) == americanize(b))
+    })
+}
+
+/// Whether replacing `original` with `replacement` only trades spellings of
+/// `dialect` for those of another variety. Such a suggestion is a matter of
+/// taste rather than a mistake; one that moves toward `dialect` is not.
+pub fn moves_away_from(dialect: Dialect, original: &str, replacement: &str) -> bool {
+    if dialect == Dialect::Any || !is_spelling_variant(original, replacement) {
+        return false;
+    }
+    let pairs = changed_words(original, replacement).unwrap_or_default();
+    pairs.iter().all(|(a, b)| {
+        let american = americanize(a);
+        match dialect {
+            Dialect::Any => false,
+            Dialect::American => *a == american,
+            Dialect::British | Dialect::Australian => *b == american,
+            // Only an -ise/-ize change: Canadian sides with the American spelling
+            Dialect::Canadian if with_z(a) == with_z(b) => *a == with_z(a),
+            Dialect::Canadian => with_z(b) == american,
+        }
+    })
+}
+
+/// The lowercased words that differ between `original` and `replacement`,
+/// or `None` when they do not line up word for word.
+fn changed_words(original: &str, replacement: &str) -> Option<Vec<(String, String)>> {
+    let words = |text: &str| -> Vec<String> {
+        text.split_whitespace()
+            .map(|w| w.trim_matches(|c: char| !c.is_alphabetic()).to_lowercase())
+            .collect()
+    };
+    let (original, replacement) = (words(original), words(replacement));
+    if original.len() != replacement.len() {
+        return None;
+    }
+    Some(
+        original
+            .into_iter()
+            .zip(replacement)
+            .filter(|(a, b)| a != b)
+            .collect(),
+    )
+}
+
+/// Spell `word` the American way where a known pattern applies.
+fn americanize(word: &str) -> String {
+    if let Some((_, american)) = VARIANT_WORDS
+        .iter()
+        .find(|(british, american)| word == *british || word == *american)
+    {
+        return american.to_string();
+    }
+    if INVARIANT_STEMS.iter().any(|stem| word.starts_with(stem)) {
+        return word.to_string();
+    }
+
+    // colour, unfavourable, neighbourhoods
+    let mut word = word.to_string();
+    if let Some(i) = OUR_STEMS
+        .iter()
+        .find_map(|stem| word.find(stem).map(|i| i + stem.len()))
+    {
+        word.replace_range(i - 3..i, "or");
+    }
+    replace_ending(&with_z(&word), VARIANT_ENDINGS)
+}
+
+/// `word` with a British `-ise`/`-yse` ending changed to `-ize`/`-yze`.
+fn with_z(word: &str) -> String {
+    replace_ending(word, Z_ENDINGS)
+}
+
+/// `word` with the longest of the British `endings` it has swapped for the
+/// American one. Very short words are left alone.
+fn replace_ending(word: &str, endings: &[(&str, &str)]) -> String {
+    let mut word = word.to_string();
+    if let Some((british, american)) = endings
+        .iter()
+        .filter(|(british, _)| word.len() > british.len() + 2 && word.ends_with(british))
+        .max_by_key(|(british, _)| british.len())
+    {
+        word.truncate(word.len() - british.len());
+        word.push_str(american);
+    }
+    word
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spelling_variants() {
+        assert!(is_spelling_variant("colour", "color"));
+        assert!(is_spelling_variant("Organised", "Organized"));
+        assert!(is_spelling_variant("the centre,", "the center,"));
+        assert!(is_spelling_variant("travelled", "traveled"));
+        assert!(is_spelling_variant("grey", "gray"));
+        assert!(is_spelling_variant("catalogue", "catalog"));
+
+        // Real corrections are not variants
+        assert!(!is_spelling_variant("four", "for"));
+        assert!(!is_spelling_variant("colour", "colors"));
+        assert!(!is_spelling_variant("I has", "I have"));
+        assert!(!is_spelling_variant("compeled", "compelled"));
+        assert!(!is_spelling_variant("color", "color"));
+        assert!(!is_spelling_variant("a color", "the colour"));
+        assert!(!is_spelling_variant("devour", "devor"));
+        assert!(!is_spelling_variant("resource", "resorce"));
+        assert!(!is_spelling_variant("encourage", "encorage"));
+        assert!(is_spelling_variant("unfavourable", "unfavorable"));
+    }
+
+    #[test]
+    fn test_variants_only_count_against_the_chosen_dialect() {
+        assert!(!moves_away_from(Dialect::Any, "colour", "color"));
+
+        assert!(moves_away_from(Dialect::British, "colour", "color"));
+        assert!(!moves_away_from(Dialect::British, "color", "colour"));
+        assert!(moves_away_from(Dialect::American, "color", "colour"));
+        assert!(!moves_away_from(Dialect::American, "colour", "color"));
+
+        // Canadian: British "our" and "tre", American "ize"
+        assert!(moves_away_from(Dialect::Canadian, "centre", "center"));
+        assert!(moves_away_from(Dialect::Canadian, "organize", "organise"));
+        assert!(!moves_away_from(Dialect::Canadian, "organise", "organize"));
+
+        // Real corrections never count
+        assert!(!moves_away_from(Dialect::British, "four", "for"));
+    }
+
+    #[test]
+    fn test_dialect_is_stored_by_short_name() {
+        #[derive(Serialize, Deserialize)]
+        struct Wrapper {
+            dialect: Dialect,
+        }
+        let json = serde_json::to_string(&Wrapper {
+            dialect: Dialect::British,
+        })
+        .unwrap();
+        assert_eq!(json, r#"{"dialect":"uk"}"#);
+        let back: Wrapper = serde_json::from_str(r#"{"dialect":"canadian"}"#).unwrap();
+        assert_eq!(back.dialect, Dialect::Canadian);
+    }
+}
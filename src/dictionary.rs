@@ -0,0 +1,54 @@
+//! The personal dictionary: words such as names, product names and code
+//! identifiers that are spelled correctly and never to be flagged.
+
+/// The word `original` stands for, without surrounding punctuation; `None`
+/// for phrases and text without letters or digits.
+pub fn word(original: &str) -> Option<&str> {
+    let word = original.trim_matches(|c: char| !c.is_alphanumeric());
+    (!word.is_empty() && !word.contains(char::is_whitespace)).then_some(word)
+}
+
+/// Whether `original` is a word in `dictionary`. Case matters, so adding
+/// `iPhone` still lets `Iphone` be flagged.
+pub fn contains(dictionary: &[String], original: &str) -> bool {
+    word(original).is_some_and(|word| dictionary.iter().any(|w| w == word))
+}
+
+/// Add the word `original` stands for, keeping the list sorted. Returns the
+/// word added, or `None` if there is no word or it is already listed.
+pub fn add(dictionary: &mut Vec<String>, original: &str) -> Option<String> {
+    let word = word(original)?;
+    match dictionary.binary_search_by(|w| w.as_str().cmp(word)) {
+        Ok(_) => None,
+        Err(i) => {
+            dictionary.insert(i, word.to_string());
+            Some(word.to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_words() {
+        assert_eq!(word("\"Grammy's\","), Some("Grammy's"));
+        assert_eq!(word("snake_case"), Some("snake_case"));
+        assert_eq!(word("two words"), None);
+        assert_eq!(word("..."), None);
+    }
+
+    #[test]
+    fn test_add_and_contains() {
+        let mut dictionary = vec!["iced".to_string()];
+        assert_eq!(add(&mut dictionary, "Grammy,"), Some("Grammy".to_string()));
+        assert_eq!(add(&mut dictionary, "Grammy"), None);
+        assert_eq!(add(&mut dictionary, "no way"), None);
+        assert_eq!(dictionary, vec!["Grammy", "iced"]);
+
+        assert!(contains(&dictionary, "(Grammy)"));
+        assert!(!contains(&dictionary, "grammy"));
+        assert!(!contains(&dictionary, "Grammy is"));
+    }
+}
@@ -0,0 +1,185 @@
+use std::fmt;
+
+use reqwest::StatusCode;
+
+use crate::config::ApiProvider;
+use crate::retry;
+
+/// Everything that can go wrong while talking to a provider.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GrammyError {
+    /// The selected provider needs an API key and none is configured.
+    MissingApiKey {
+        provider: ApiProvider,
+    },
+    /// The HTTP client could not be set up.
+    Client(String),
+    /// The request never got a response (DNS, refused, reset, ...).
+    Network(String),
+    Timeout,
+    /// The provider answered with a non-success status.
+    Http {
+        provider: ApiProvider,
+        status: StatusCode,
+        message: String,
+    },
+    /// The provider answered 429 and retrying didn't help.
+    RateLimited {
+        provider: ApiProvider,
+        message: String,
+    },
+    /// The provider reported an error in the middle of a streamed response.
+    Stream {
+        provider: ApiProvider,
+        message: String,
+    },
+    /// The response envelope wasn't what the provider's API documents.
+    InvalidResponse(String),
+    /// The model's output didn't match the `LlmResponse` schema.
+    InvalidLlmJson(String),
+    ModelNotFound {
+        provider: ApiProvider,
+        model: String,
+    },
+    /// The app's own plumbing failed (e.g. the API thread died).
+    Internal(String),
+}
+
+impl GrammyError {
+    /// Failures that might go away if the same request is sent again. Apart
+    /// from timeouts, which fall back straight away, `send_with_retry`
+    /// retries these before giving up.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            GrammyError::Network(_) | GrammyError::Timeout | GrammyError::RateLimited { .. } => {
+                true
+            }
+            GrammyError::Http { status, .. } => retry::is_retryable_status(*status),
+            _ => false,
+        }
+    }
+
+    /// Failures of the provider or model that answered, which a fallback
+    /// backend may not run into: transient ones, a broken stream, output that
+    /// could not be read and other server errors. Problems with the request
+    /// or the user's settings would follow the check to the next backend.
+    pub fn should_fall_back(&self) -> bool {
+        match self {
+            GrammyError::Stream { .. } | GrammyError::InvalidLlmJson(_) => true,
+            GrammyError::Http { status, .. } => status.is_server_error(),
+            _ => self.is_retryable(),
+        }
+    }
+
+    /// Failures the user can only fix by changing their settings.
+    pub fn needs_settings(&self) -> bool {
+        match self {
+            // Proxy and certificate settings the client could not be built with
+            GrammyError::Client(_) => true,
+            GrammyError::MissingApiKey { .. } | GrammyError::ModelNotFound { .. } => true,
+            GrammyError::Http { status, .. } => matches!(
+                *status,
+                StatusCode::UNAUTHORIZED
+                    | StatusCode::FORBIDDEN
+                    | StatusCode::NOT_FOUND
+                    | StatusCode::PAYMENT_REQUIRED
+            ),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for GrammyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GrammyError::MissingApiKey { .. } => {
+                write!(f, "API key not set. Click ⚙ to configure.")
+            }
+            GrammyError::Client(e) => write!(f, "Failed to create HTTP client: {}", e),
+            GrammyError::Network(e) => write!(f, "Network error: {}", e),
+            GrammyError::Timeout => write!(f, "Request timed out"),
+            GrammyError::Http {
+                provider,
+                status,
+                message,
+            } => write!(f, "{} error ({}): {}", provider.name(), status, message),
+            GrammyError::RateLimited { provider, message } => {
+                write!(f, "{} rate limited: {}", provider.name(), message)
+            }
+            GrammyError::Stream { provider, message } => {
+                write!(f, "{} error: {}", provider.name(), message)
+            }
+            GrammyError::InvalidResponse(e) => write!(f, "Failed to parse response: {}", e),
+            GrammyError::InvalidLlmJson(e) => write!(f, "Invalid JSON from LLM: {}", e),
+            GrammyError::ModelNotFound { provider, model } => {
+                write!(f, "Model '{}' not found for {}", model, provider.name())
+            }
+            GrammyError::Internal(e) => write!(f, "Internal error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for GrammyError {}
+
+/// The error and its causes, e.g. `error sending request: proxy authentication
+/// required`; reqwest's own message leaves out why the connection failed.
+fn describe_chain(e: &dyn std::error::Error) -> String {
+    let mut message = e.to_string();
+    let mut source = e.source();
+    while let Some(cause) = source {
+        let cause = cause.to_string();
+        if !message.contains(&cause) {
+            message.push_str(": ");
+            message.push_str(&cause);
+        }
+        source = source.and_then(|s| s.source());
+    }
+    message
+}
+
+impl From<reqwest::Error> for GrammyError {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_timeout() {
+            GrammyError::Timeout
+        } else if e.is_decode() {
+            GrammyError::InvalidResponse(e.to_string())
+        } else {
+            GrammyError::Network(describe_chain(&e))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_http_error_classification() {
+        let unauthorized = GrammyError::Http {
+            provider: ApiProvider::OpenAI,
+            status: StatusCode::UNAUTHORIZED,
+            message: "Incorrect API key".to_string(),
+        };
+        assert!(unauthorized.needs_settings());
+        assert!(!unauthorized.is_retryable());
+        assert!(!unauthorized.should_fall_back());
+        assert_eq!(
+            unauthorized.to_string(),
+            "OpenAI error (401 Unauthorized): Incorrect API key"
+        );
+
+        let unavailable = GrammyError::Http {
+            provider: ApiProvider::OpenRouter,
+            status: StatusCode::SERVICE_UNAVAILABLE,
+            message: String::new(),
+        };
+        assert!(unavailable.is_retryable());
+        assert!(unavailable.should_fall_back());
+        assert!(!unavailable.needs_settings());
+
+        // Not retried as is, but another model may answer properly
+        let garbled = GrammyError::InvalidLlmJson("expected value".to_string());
+        assert!(!garbled.is_retryable());
+        assert!(garbled.should_fall_back());
+    }
+}
@@ -0,0 +1,93 @@
+//! Which language a document is written in: picked in settings, or guessed
+//! locally from the text before it is sent.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+pub use whatlang::Lang;
+
+/// The document language setting. Stored as `"auto"` or an ISO 639-3 code
+/// such as `"por"`; unknown codes read as `Auto`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum LanguageSetting {
+    #[default]
+    Auto,
+    Fixed(Lang),
+}
+
+impl LanguageSetting {
+    /// Auto-detection followed by every language the detector knows, by name.
+    pub fn all() -> Vec<LanguageSetting> {
+        let mut languages = Lang::all().to_vec();
+        languages.sort_by_key(|l| l.eng_name());
+        std::iter::once(LanguageSetting::Auto)
+            .chain(languages.into_iter().map(LanguageSetting::Fixed))
+            .collect()
+    }
+}
+
+impl From<String> for LanguageSetting {
+    fn from(code: String) -> Self {
+        Lang::from_code(code).map_or(LanguageSetting::Auto, LanguageSetting::Fixed)
+    }
+}
+
+impl From<LanguageSetting> for String {
+    fn from(setting: LanguageSetting) -> Self {
+        match setting {
+            LanguageSetting::Auto => "auto".to_string(),
+            LanguageSetting::Fixed(lang) => lang.code().to_string(),
+        }
+    }
+}
+
+impl fmt::Display for LanguageSetting {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LanguageSetting::Auto => write!(f, "Auto-detect"),
+            LanguageSetting::Fixed(lang) => write!(f, "{}", lang.eng_name()),
+        }
+    }
+}
+
+/// The language `text` is written in, if the detector is confident. Short
+/// snippets and mixed-language text often give no answer.
+pub fn detect(text: &str) -> Option<Lang> {
+    whatlang::detect(text)
+        .filter(|info| info.is_reliable())
+        .map(|info| info.lang())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(
+            detect("Eu escrevo em português todos os dias, e o corretor precisa de entender isso."),
+            Some(Lang::Por)
+        );
+        assert_eq!(
+            detect("Ich schreibe jeden Tag auf Deutsch, und die Prüfung sollte das verstehen."),
+            Some(Lang::Deu)
+        );
+        assert_eq!(detect("ok"), None);
+    }
+
+    #[test]
+    fn test_setting_round_trips_as_code() {
+        assert_eq!(String::from(LanguageSetting::Fixed(Lang::Spa)), "spa");
+        assert_eq!(
+            LanguageSetting::from("spa".to_string()),
+            LanguageSetting::Fixed(Lang::Spa)
+        );
+        assert_eq!(
+            LanguageSetting::from("klingon".to_string()),
+            LanguageSetting::Auto
+        );
+        assert_eq!(LanguageSetting::all()[0], LanguageSetting::Auto);
+    }
+}
@@ -1,4 +1,20 @@
 pub mod api;
 pub mod app;
+pub mod cache;
+pub mod chunking;
 pub mod config;
+pub mod dialect;
+pub mod dictionary;
+pub mod error;
+pub mod language;
+#[cfg(any(test, feature = "mock"))]
+pub mod mock;
+pub mod network;
+pub mod prompt;
+pub mod provider;
+pub mod recovery;
+pub mod retry;
+pub mod streaming;
+pub mod style_guide;
 pub mod suggestion;
+pub mod usage;
@@ -1,12 +1,17 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+use std::path::PathBuf;
+
 use grammy::app;
 
 use iced::window;
 use iced::Size;
 
 fn main() -> iced::Result {
-    iced::application(app::new, app::update, app::view)
+    // `grammy path/to/file` checks that file's text; it is never written back
+    let file = std::env::args_os().nth(1).map(PathBuf::from);
+
+    iced::application(move || app::new(file.clone()), app::update, app::view)
         .title("Grammy")
         .theme(app::theme)
         .subscription(app::subscription)
@@ -0,0 +1,298 @@
+//! A local HTTP stand-in for the provider APIs, so the request flows can be
+//! exercised offline and deterministically.
+//!
+//! Start a [`MockServer`], point an [`Endpoint`](crate::provider::Endpoint) at
+//! [`MockServer::url`], and queue the responses each path should get:
+//!
+//! ```no_run
+//! # async fn example() {
+//! use grammy::config::ApiProvider;
+//! use grammy::mock::{MockResponse, MockServer};
+//! use grammy::provider::Endpoint;
+//!
+//! let server = MockServer::start().await;
+//! server.respond("/chat/completions", MockResponse::openai_chat(r#"{"matches":[]}"#));
+//! let endpoint = Endpoint::new(ApiProvider::OpenAI, "key".into(), server.url());
+//! # }
+//! ```
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde_json::{json, Value};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+
+/// A canned HTTP response.
+#[derive(Debug, Clone)]
+pub struct MockResponse {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: String,
+    delay: Duration,
+}
+
+impl MockResponse {
+    /// A response with an arbitrary body, e.g. deliberately broken JSON.
+    pub fn text(status: u16, body: impl Into<String>) -> Self {
+        Self {
+            status,
+            headers: vec![("Content-Type".to_string(), "text/plain".to_string())],
+            body: body.into(),
+            delay: Duration::ZERO,
+        }
+    }
+
+    pub fn json(status: u16, body: Value) -> Self {
+        Self::text(status, body.to_string()).header("Content-Type", "application/json")
+    }
+
+    /// A `text/event-stream` body with one `data:` event per item.
+    pub fn sse<S: AsRef<str>>(events: &[S]) -> Self {
+        let body: String = events
+            .iter()
+            .map(|e| format!("data: {}\n\n", e.as_ref()))
+            .collect();
+        Self::text(200, body).header("Content-Type", "text/event-stream")
+    }
+
+    /// The error body shape shared by OpenAI, OpenRouter and Gemini.
+    pub fn error(status: u16, message: &str) -> Self {
+        Self::json(status, json!({ "error": { "message": message } }))
+    }
+
+    /// A successful OpenAI/OpenRouter chat completion answering `content`.
+    pub fn openai_chat(content: &str) -> Self {
+        Self::json(
+            200,
+            json!({
+                "choices": [{ "message": { "role": "assistant", "content": content } }],
+                "usage": { "prompt_tokens": 100, "completion_tokens": 20 }
+            }),
+        )
+    }
+
+    /// A streamed OpenAI/OpenRouter chat completion sending `content` in
+    /// pieces, with usage in the last chunk.
+    pub fn openai_stream(pieces: &[&str]) -> Self {
+        let mut events: Vec<String> = pieces
+            .iter()
+            .map(|piece| json!({ "choices": [{ "delta": { "content": piece } }] }).to_string())
+            .collect();
+        events.push(
+            json!({ "choices": [], "usage": { "prompt_tokens": 100, "completion_tokens": 20 } })
+                .to_string(),
+        );
+        events.push("[DONE]".to_string());
+        Self::sse(&events)
+    }
+
+    /// A successful Gemini `generateContent` response answering `content`.
+    pub fn gemini_chat(content: &str) -> Self {
+        Self::json(
+            200,
+            json!({
+                "candidates": [{ "content": { "role": "model", "parts": [{ "text": content }] } }],
+                "usageMetadata": { "promptTokenCount": 100, "candidatesTokenCount": 20 }
+            }),
+        )
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers
+            .retain(|(existing, _)| !existing.eq_ignore_ascii_case(name));
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Wait this long before answering, e.g. to trigger client timeouts.
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+}
+
+/// A request the server received.
+#[derive(Debug, Clone)]
+pub struct RecordedRequest {
+    pub method: String,
+    /// Path including the query string.
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl RecordedRequest {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn json(&self) -> Value {
+        serde_json::from_str(&self.body).unwrap_or(Value::Null)
+    }
+}
+
+struct Route {
+    path: String,
+    responses: VecDeque<MockResponse>,
+}
+
+#[derive(Default)]
+struct Shared {
+    routes: Mutex<Vec<Route>>,
+    requests: Mutex<Vec<RecordedRequest>>,
+}
+
+/// An HTTP server on a random local port answering from queued responses.
+/// Requests nothing was queued for get a 404.
+pub struct MockServer {
+    url: String,
+    shared: Arc<Shared>,
+    task: JoinHandle<()>,
+}
+
+impl MockServer {
+    /// Start listening. Must be called from within a tokio runtime.
+    pub async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("failed to bind mock server");
+        let url = format!(
+            "http://{}",
+            listener.local_addr().expect("no local address")
+        );
+        let shared = Arc::new(Shared::default());
+
+        let server = shared.clone();
+        let task = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(serve(stream, server.clone()));
+            }
+        });
+
+        Self { url, shared, task }
+    }
+
+    /// Base URL to use in place of a provider's API root.
+    pub fn url(&self) -> String {
+        self.url.clone()
+    }
+
+    /// Queue `response` for the next request whose path contains `path`.
+    /// Responses for the same path are served in the order they were queued.
+    pub fn respond(&self, path: &str, response: MockResponse) {
+        let mut routes = self.shared.routes.lock().unwrap();
+        match routes.iter_mut().find(|r| r.path == path) {
+            Some(route) => route.responses.push_back(response),
+            None => routes.push(Route {
+                path: path.to_string(),
+                responses: VecDeque::from([response]),
+            }),
+        }
+    }
+
+    /// Every request received so far, in order of arrival.
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.shared.requests.lock().unwrap().clone()
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn serve(mut stream: TcpStream, shared: Arc<Shared>) {
+    let Some(request) = read_request(&mut stream).await else {
+        return;
+    };
+
+    let response = {
+        let mut routes = shared.routes.lock().unwrap();
+        routes
+            .iter_mut()
+            .find(|r| request.path.contains(&r.path) && !r.responses.is_empty())
+            .and_then(|r| r.responses.pop_front())
+    }
+    .unwrap_or_else(|| {
+        MockResponse::error(
+            404,
+            &format!("no mock response queued for {}", request.path),
+        )
+    });
+    shared.requests.lock().unwrap().push(request);
+    tokio::time::sleep(response.delay).await;
+
+    let reason = reqwest::StatusCode::from_u16(response.status)
+        .ok()
+        .and_then(|s| s.canonical_reason())
+        .unwrap_or("");
+    let mut head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+        response.status,
+        reason,
+        response.body.len()
+    );
+    for (name, value) in &response.headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str("\r\n");
+
+    let _ = stream.write_all(head.as_bytes()).await;
+    let _ = stream.write_all(response.body.as_bytes()).await;
+    let _ = stream.shutdown().await;
+}
+
+async fn read_request(stream: &mut TcpStream) -> Option<RecordedRequest> {
+    let mut buffer = Vec::new();
+    let mut chunk = [0u8; 4096];
+
+    let head_end = loop {
+        if let Some(i) = buffer.windows(4).position(|w| w == b"\r\n\r\n") {
+            break i + 4;
+        }
+        let n = stream.read(&mut chunk).await.ok()?;
+        if n == 0 {
+            return None;
+        }
+        buffer.extend_from_slice(&chunk[..n]);
+    };
+
+    let head = String::from_utf8_lossy(&buffer[..head_end]).to_string();
+    let mut lines = head.lines();
+    let mut request_line = lines.next()?.split_whitespace();
+    let method = request_line.next()?.to_string();
+    let path = request_line.next()?.to_string();
+    let headers: Vec<(String, String)> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(n, v)| (n.trim().to_string(), v.trim().to_string()))
+        .collect();
+
+    let length: usize = headers
+        .iter()
+        .find(|(n, _)| n.eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, v)| v.parse().ok())
+        .unwrap_or(0);
+    while buffer.len() < head_end + length {
+        let n = stream.read(&mut chunk).await.ok()?;
+        if n == 0 {
+            break;
+        }
+        buffer.extend_from_slice(&chunk[..n]);
+    }
+    let body_end = buffer.len().min(head_end + length);
+    let body = String::from_utf8_lossy(&buffer[head_end..body_end]).to_string();
+
+    Some(RecordedRequest {
+        method,
+        path,
+        headers,
+        body,
+    })
+}
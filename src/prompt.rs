@@ -0,0 +1,228 @@
+//! The system prompt sent with every check: the built-in template, templates
+//! defined in the config file, and filling in their placeholders.
+//!
+//! Templates may use these placeholders:
+//!
+//! - `{language}`: the language of the text, e.g. `English`
+//! - `{dialect}`: a line asking for a regional spelling, or nothing
+//! - `{style_rules}`: a list of house rules and dictionary words, or nothing;
+//!   appended when missing, so a template cannot drop the team's rules
+//! - `{schema}`: the answer format the app parses; appended when missing,
+//!   since answers in any other shape cannot be read
+
+use serde::{Deserialize, Serialize};
+
+use crate::suggestion::Severity;
+
+/// Name under which the built-in template is offered.
+pub const DEFAULT_TEMPLATE_NAME: &str = "Default";
+
+pub const DEFAULT_TEMPLATE: &str = r#"You are a strict {language} writing assistant.
+Your job: suggest edits ONLY for:
+1. Grammatical errors.
+2. Typos.
+3. Phrases that are clearly awkward or non-native sounding.
+{dialect}
+Rules:
+- Do NOT suggest stylistic variations if the original is correct.
+- Do NOT rewrite the text.
+- If a sentence is grammatically correct and clear, do NOT suggest anything.
+- If you have a comment (e.g., ambiguity) but no specific correction, leave "replacement" as null.
+{style_rules}
+{schema}"#;
+
+/// Fields of an answer entry with the example value shown to the model;
+/// `severity` is listed separately. Kept in step with `LlmResponse` by a test.
+const MATCH_FIELDS: &[(&str, &str)] = &[
+    ("message", "explanation of the error"),
+    ("original", "exact text to replace"),
+    ("replacement", "corrected text or null"),
+];
+
+/// A named system prompt from the config file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PromptTemplate {
+    pub name: String,
+    pub text: String,
+}
+
+/// A template together with the values for its placeholders.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Prompt {
+    pub template: String,
+    pub language: String,
+    /// e.g. `British English`, see `Dialect::prompt_name`; empty for no
+    /// preference.
+    pub dialect: String,
+    pub style_rules: Vec<String>,
+    /// Words from the personal dictionary, known to be spelled correctly.
+    pub dictionary: Vec<String>,
+}
+
+impl Default for Prompt {
+    fn default() -> Self {
+        Self {
+            template: DEFAULT_TEMPLATE.to_string(),
+            language: "English".to_string(),
+            dialect: String::new(),
+            style_rules: Vec::new(),
+            dictionary: Vec::new(),
+        }
+    }
+}
+
+impl Prompt {
+    /// The system prompt to send.
+    pub fn render(&self) -> String {
+        let dialect = if self.dialect.is_empty() {
+            String::new()
+        } else {
+            format!(
+                "\nUse {} spelling and usage throughout. Changing a correct spelling from another variety to it is a \"{}\" suggestion, never an error.\n",
+                self.dialect,
+                Severity::Variant.name()
+            )
+        };
+        let mut style_rules = if self.style_rules.is_empty() {
+            String::new()
+        } else {
+            let rules: String = self
+                .style_rules
+                .iter()
+                .map(|rule| format!("- {}\n", rule))
+                .collect();
+            format!(
+                "\nHouse rules, which take precedence over the above:\n{}",
+                rules
+            )
+        };
+        if !self.dictionary.is_empty() {
+            style_rules.push_str(&format!(
+                "\nThese words are spelled correctly; never flag them: {}\n",
+                self.dictionary.join(", ")
+            ));
+        }
+
+        let mut template = self.template.clone();
+        if !template.contains("{style_rules}") {
+            template.push_str("\n\n{style_rules}");
+        }
+        if !template.contains("{schema}") {
+            template.push_str("\n\n{schema}");
+        }
+        let rendered = template
+            .replace("{language}", &self.language)
+            .replace("{dialect}", &dialect)
+            .replace("{style_rules}", &style_rules)
+            .replace("{schema}", &format!("\n{}", schema_section()));
+        collapse_blank_lines(rendered.trim())
+    }
+}
+
+/// The answer format, generated from the fields of `LlmResponse` and the
+/// severity levels so the prompt cannot drift from what the app parses.
+pub fn schema_section() -> String {
+    let fields: String = MATCH_FIELDS
+        .iter()
+        .map(|(name, example)| format!("      \"{}\": \"{}\",\n", name, example))
+        .collect();
+    let severities: Vec<&str> = Severity::ALL.iter().map(|s| s.name()).collect();
+    let levels: String = Severity::ALL
+        .iter()
+        .map(|s| format!("- \"{}\": {}\n", s.name(), s.description()))
+        .collect();
+
+    format!(
+        r#"Return ONLY valid JSON with this exact schema:
+{{
+  "matches": [
+    {{
+{}      "severity": "{}"
+    }}
+  ]
+}}
+
+Severity levels:
+{}
+IMPORTANT: The "original" field must contain the EXACT substring from the input (copy it precisely, including spacing).
+If there is nothing to change, return {{"matches": []}}."#,
+        fields,
+        severities.join("|"),
+        levels
+    )
+}
+
+/// Squeeze the gaps left by empty placeholders down to one blank line.
+fn collapse_blank_lines(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut blank_lines = 0;
+    for line in text.lines() {
+        if line.trim().is_empty() {
+            blank_lines += 1;
+            if blank_lines > 1 {
+                continue;
+            }
+        } else {
+            blank_lines = 0;
+        }
+        result.push_str(line.trim_end());
+        result.push('\n');
+    }
+    result.trim_end().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::suggestion::LlmResponse;
+
+    #[test]
+    fn test_default_prompt() {
+        let prompt = Prompt::default().render();
+        assert!(prompt.starts_with("You are a strict English writing assistant."));
+        assert!(prompt.contains(r#""severity": "error|warning|suggestion|variant""#));
+        assert!(!prompt.contains("{language}"));
+        assert!(!prompt.contains("\n\n\n"));
+    }
+
+    #[test]
+    fn test_placeholders_and_schema_section() {
+        let prompt = Prompt {
+            template: "Check this {language} text.{dialect}{style_rules}".to_string(),
+            language: "Portuguese".to_string(),
+            dialect: "European Portuguese".to_string(),
+            style_rules: vec!["Use the Oxford comma.".to_string()],
+            dictionary: vec!["Grammy".to_string(), "iced".to_string()],
+        }
+        .render();
+        assert!(prompt.starts_with("Check this Portuguese text.\nUse European Portuguese spelling"));
+        assert!(prompt.contains("- Use the Oxford comma."));
+        assert!(prompt.contains("never flag them: Grammy, iced\n"));
+        // Templates without the schema placeholder still get the format
+        assert!(prompt.ends_with(r#"If there is nothing to change, return {"matches": []}."#));
+    }
+
+    #[test]
+    fn test_style_rules_are_appended_when_missing() {
+        let prompt = Prompt {
+            template: "Check this {language} text.".to_string(),
+            style_rules: vec!["Use the Oxford comma.".to_string()],
+            dictionary: vec!["Grammy".to_string()],
+            ..Prompt::default()
+        }
+        .render();
+        assert!(prompt.starts_with("Check this English text.\n\nHouse rules"));
+        assert!(prompt.contains("- Use the Oxford comma."));
+        assert!(prompt.contains("never flag them: Grammy\n"));
+        assert!(prompt.ends_with(r#"If there is nothing to change, return {"matches": []}."#));
+    }
+
+    #[test]
+    fn test_schema_section_matches_response_fields() {
+        let schema = LlmResponse::json_schema();
+        let required = &schema["properties"]["matches"]["items"]["required"];
+        let mut fields: Vec<&str> = MATCH_FIELDS.iter().map(|(name, _)| *name).collect();
+        fields.push("severity");
+        assert_eq!(*required, serde_json::json!(fields));
+    }
+}
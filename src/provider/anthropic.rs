@@ -0,0 +1,117 @@
+use reqwest::{Client, RequestBuilder};
+use serde_json::{json, Value};
+
+use super::{ChatRequest, ModelInfo, Provider};
+use crate::config::ApiProvider;
+use crate::usage::Usage;
+
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+/// Sent as `max_tokens`, which the API requires; every Claude model allows at
+/// least this many.
+const MAX_TOKENS: u32 = 4096;
+
+/// Anthropic's native Messages API (`/v1/messages`).
+pub struct Anthropic {
+    base_url: String,
+    api_key: String,
+}
+
+impl Anthropic {
+    pub fn new(base_url: String, api_key: String) -> Self {
+        Self { base_url, api_key }
+    }
+
+    fn authorize(&self, request: RequestBuilder) -> RequestBuilder {
+        request
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", ANTHROPIC_VERSION)
+    }
+}
+
+impl Provider for Anthropic {
+    fn kind(&self) -> ApiProvider {
+        ApiProvider::Anthropic
+    }
+
+    fn max_answer_tokens(&self) -> u64 {
+        MAX_TOKENS.into()
+    }
+
+    fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    fn has_credentials(&self) -> bool {
+        !self.api_key.is_empty()
+    }
+
+    fn chat_request(&self, client: &Client, chat: &ChatRequest<'_>) -> RequestBuilder {
+        // The system prompt is a top-level field, not a message
+        let mut messages: Vec<Value> = chat
+            .history
+            .iter()
+            .map(|entry| json!({ "role": entry.role, "content": entry.content }))
+            .collect();
+        messages.push(json!({ "role": "user", "content": chat.user_message }));
+
+        let body = json!({
+            "model": chat.model,
+            "max_tokens": MAX_TOKENS,
+            "system": chat.system_prompt,
+            "messages": messages,
+            "stream": chat.stream,
+        });
+
+        self.authorize(client.post(format!("{}/messages", self.base_url)))
+            .header("Content-Type", "application/json")
+            .json(&body)
+    }
+
+    fn extract_content(&self, body: &Value) -> Option<String> {
+        // The response is a list of content blocks; we only care about text ones.
+        let blocks = body["content"].as_array()?;
+        let text: String = blocks
+            .iter()
+            .filter(|b| b["type"] == "text")
+            .filter_map(|b| b["text"].as_str())
+            .collect();
+        Some(text)
+    }
+
+    fn extract_stream_delta(&self, event: &Value) -> Option<String> {
+        if event["type"] != "content_block_delta" || event["delta"]["type"] != "text_delta" {
+            return None;
+        }
+        event["delta"]["text"].as_str().map(|s| s.to_string())
+    }
+
+    fn models_request(&self, client: &Client) -> RequestBuilder {
+        self.authorize(client.get(format!("{}/models?limit=1000", self.base_url)))
+    }
+
+    fn extract_usage(&self, body: &Value) -> Option<Usage> {
+        // Streams report input tokens in `message_start` and the running
+        // output count in `message_delta`
+        let usage = body.get("usage").or_else(|| body["message"].get("usage"))?;
+        Some(Usage {
+            prompt_tokens: usage["input_tokens"].as_u64().unwrap_or(0),
+            completion_tokens: usage["output_tokens"].as_u64().unwrap_or(0),
+        })
+    }
+
+    fn parse_models(&self, body: &Value) -> Vec<ModelInfo> {
+        body["data"]
+            .as_array()
+            .map(|models| {
+                models
+                    .iter()
+                    .filter_map(|m| m["id"].as_str().map(ModelInfo::new))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    fn health_check_request(&self, client: &Client) -> RequestBuilder {
+        self.models_request(client)
+    }
+}
@@ -0,0 +1,177 @@
+use reqwest::{Client, RequestBuilder};
+use serde_json::{json, Value};
+
+use super::{ChatRequest, ModelInfo, Provider};
+use crate::config::ApiProvider;
+use crate::usage::Usage;
+
+/// Google's Generative Language API (`generateContent`).
+pub struct Gemini {
+    base_url: String,
+    api_key: String,
+}
+
+impl Gemini {
+    pub fn new(base_url: String, api_key: String) -> Self {
+        Self { base_url, api_key }
+    }
+}
+
+impl Provider for Gemini {
+    fn kind(&self) -> ApiProvider {
+        ApiProvider::Gemini
+    }
+
+    fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    fn has_credentials(&self) -> bool {
+        !self.api_key.is_empty()
+    }
+
+    fn chat_request(&self, client: &Client, chat: &ChatRequest<'_>) -> RequestBuilder {
+        let url = if chat.stream {
+            format!(
+                "{}/models/{}:streamGenerateContent?alt=sse&key={}",
+                self.base_url, chat.model, self.api_key
+            )
+        } else {
+            format!(
+                "{}/models/{}:generateContent?key={}",
+                self.base_url, chat.model, self.api_key
+            )
+        };
+        // Gemini calls the assistant role "model"
+        let mut contents: Vec<Value> = chat
+            .history
+            .iter()
+            .map(|entry| {
+                let role = if entry.role == "assistant" {
+                    "model"
+                } else {
+                    "user"
+                };
+                json!({ "role": role, "parts": [{ "text": entry.content }] })
+            })
+            .collect();
+        contents.push(json!({ "role": "user", "parts": [{ "text": chat.user_message }] }));
+
+        let mut body = json!({
+            "systemInstruction": {
+                "parts": [{ "text": chat.system_prompt }]
+            },
+            "contents": contents,
+            "generationConfig": {
+                "responseMimeType": "application/json"
+            }
+        });
+        if let Some(schema) = chat.response_schema {
+            body["generationConfig"]["responseSchema"] = gemini_schema(schema);
+        }
+        client
+            .post(url)
+            .header("Content-Type", "application/json")
+            .json(&body)
+    }
+
+    fn extract_content(&self, body: &Value) -> Option<String> {
+        body["candidates"][0]["content"]["parts"][0]["text"]
+            .as_str()
+            .map(|s| s.to_string())
+    }
+
+    fn extract_stream_delta(&self, event: &Value) -> Option<String> {
+        // Each streamed chunk has the same shape as a full response
+        self.extract_content(event)
+    }
+
+    fn models_request(&self, client: &Client) -> RequestBuilder {
+        client.get(format!("{}/models?key={}", self.base_url, self.api_key))
+    }
+
+    fn extract_usage(&self, body: &Value) -> Option<Usage> {
+        let usage = &body["usageMetadata"];
+        // Thinking tokens are billed as output
+        let output = usage["candidatesTokenCount"].as_u64().unwrap_or(0)
+            + usage["thoughtsTokenCount"].as_u64().unwrap_or(0);
+        Some(Usage {
+            prompt_tokens: usage["promptTokenCount"].as_u64()?,
+            completion_tokens: output,
+        })
+    }
+
+    fn parse_models(&self, body: &Value) -> Vec<ModelInfo> {
+        body["models"]
+            .as_array()
+            .map(|models| {
+                models
+                    .iter()
+                    .filter_map(|m| {
+                        let name = m["name"].as_str()?;
+                        // Gemini returns "models/gemini-pro", we want just "gemini-pro"
+                        let mut info = ModelInfo::new(name.strip_prefix("models/").unwrap_or(name));
+                        info.context_length = m["inputTokenLimit"].as_u64();
+                        // Every model that generates content takes a responseSchema
+                        info.structured_output =
+                            m["supportedGenerationMethods"].as_array().map(|methods| {
+                                methods.iter().any(|method| method == "generateContent")
+                            });
+                        Some(info)
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    fn health_check_request(&self, client: &Client) -> RequestBuilder {
+        self.models_request(client)
+    }
+}
+
+/// Translate a JSON Schema into the OpenAPI subset Gemini's `responseSchema`
+/// accepts: upper-case type names, `nullable` instead of type unions, and no
+/// `additionalProperties`.
+fn gemini_schema(schema: &Value) -> Value {
+    let Some(object) = schema.as_object() else {
+        return schema.clone();
+    };
+
+    let mut out = serde_json::Map::new();
+    for (key, value) in object {
+        match key.as_str() {
+            "type" => {
+                let types: Vec<&str> = match value {
+                    Value::Array(types) => types.iter().filter_map(|t| t.as_str()).collect(),
+                    other => other.as_str().into_iter().collect(),
+                };
+                if let Some(t) = types.iter().find(|t| **t != "null") {
+                    out.insert("type".to_string(), json!(t.to_uppercase()));
+                }
+                if types.contains(&"null") {
+                    out.insert("nullable".to_string(), json!(true));
+                }
+            }
+            "additionalProperties" => {}
+            "properties" => {
+                let properties = value
+                    .as_object()
+                    .map(|props| {
+                        props
+                            .iter()
+                            .map(|(name, prop)| (name.clone(), gemini_schema(prop)))
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                out.insert(key.clone(), Value::Object(properties));
+            }
+            "items" => {
+                out.insert(key.clone(), gemini_schema(value));
+            }
+            _ => {
+                out.insert(key.clone(), value.clone());
+            }
+        }
+    }
+    Value::Object(out)
+}
@@ -0,0 +1,411 @@
+//! Backend-specific request building and response parsing.
+//!
+//! `api` drives the request flow (logging, status handling, JSON decoding) and
+//! asks a [`Provider`] for everything that differs between backends: URLs,
+//! authentication, request body shape and where the interesting bits live in
+//! the response.
+
+mod anthropic;
+mod gemini;
+mod openai;
+
+pub use anthropic::Anthropic;
+pub use gemini::Gemini;
+pub use openai::{OpenAiCompatible, OpenRouter};
+
+use reqwest::{Client, RequestBuilder};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::app::history::HistoryEntry;
+use crate::chunking;
+use crate::config::ApiProvider;
+use crate::usage::{ModelPricing, Usage};
+
+/// Everything a backend needs to build a grammar-check request.
+#[derive(Debug, Clone, Copy)]
+pub struct ChatRequest<'a> {
+    pub model: &'a str,
+    pub system_prompt: &'a str,
+    pub history: &'a [HistoryEntry],
+    pub user_message: &'a str,
+    /// Ask for a server-sent event stream instead of a single response.
+    pub stream: bool,
+    /// JSON Schema the response must follow, for backends that can enforce
+    /// one. `None` asks for any JSON object.
+    pub response_schema: Option<&'a Value>,
+}
+
+/// One entry of a provider's model listing. Apart from the ID, each field is
+/// only known for providers whose listing includes it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModelInfo {
+    pub id: String,
+    #[serde(default)]
+    pub pricing: Option<ModelPricing>,
+    /// Input tokens the model accepts.
+    #[serde(default)]
+    pub context_length: Option<u64>,
+    /// Whether the model can be held to a JSON schema.
+    #[serde(default)]
+    pub structured_output: Option<bool>,
+    /// What goes in and comes out, e.g. `text+image->text`.
+    #[serde(default)]
+    pub modality: Option<String>,
+}
+
+impl ModelInfo {
+    pub fn new(id: impl Into<String>) -> Self {
+        Self {
+            id: id.into(),
+            pricing: None,
+            context_length: None,
+            structured_output: None,
+            modality: None,
+        }
+    }
+}
+
+pub trait Provider: Send + Sync {
+    /// The configured provider this implementation talks to.
+    fn kind(&self) -> ApiProvider;
+
+    /// The URL requests are sent under, e.g. `https://api.openai.com/v1`.
+    fn base_url(&self) -> &str;
+
+    /// Whether the provider has the credentials it needs to make requests.
+    fn has_credentials(&self) -> bool;
+
+    /// Build the chat/completion request asking for a JSON response, constrained
+    /// to `chat.response_schema` where the backend supports it.
+    fn chat_request(&self, client: &Client, chat: &ChatRequest<'_>) -> RequestBuilder;
+
+    /// Pull the model's text output out of a successful chat response.
+    fn extract_content(&self, body: &Value) -> Option<String>;
+
+    /// Pull the newly generated text out of one event of a streamed response.
+    fn extract_stream_delta(&self, event: &Value) -> Option<String>;
+
+    /// Pull the token counts out of a chat response or one event of a streamed one.
+    fn extract_usage(&self, body: &Value) -> Option<Usage>;
+
+    /// Pull a human-readable message out of an error response body.
+    fn error_message(&self, body: &Value) -> Option<String> {
+        body.get("error")
+            .and_then(|e| e.get("message"))
+            .and_then(|m| m.as_str())
+            .or_else(|| body.get("message").and_then(|m| m.as_str()))
+            .map(|s| s.to_string())
+    }
+
+    /// Build the request listing the models available to this account.
+    fn models_request(&self, client: &Client) -> RequestBuilder;
+
+    /// Extract the models from a model listing response.
+    fn parse_models(&self, body: &Value) -> Vec<ModelInfo>;
+
+    /// Build a cheap request that succeeds only if the URL and credentials are valid.
+    fn health_check_request(&self, client: &Client) -> RequestBuilder;
+
+    /// Longest answer to plan for, in tokens. Text is split so its answer
+    /// fits; a longer one would be cut off mid-JSON.
+    fn max_answer_tokens(&self) -> u64 {
+        chunking::DEFAULT_ANSWER_TOKENS
+    }
+}
+
+/// Where and how to reach a provider: plain data that can be sent to the API worker.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Endpoint {
+    pub provider: ApiProvider,
+    pub api_key: String,
+    pub base_url: String,
+}
+
+impl Endpoint {
+    pub fn new(provider: ApiProvider, api_key: String, base_url: String) -> Self {
+        Self {
+            provider,
+            api_key,
+            base_url: base_url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    /// Endpoint for a built-in provider at its default URL.
+    pub fn with_default_url(provider: ApiProvider, api_key: String) -> Self {
+        let base_url = provider.base_url().to_string();
+        Self::new(provider, api_key, base_url)
+    }
+
+    pub fn build(&self) -> Box<dyn Provider> {
+        let api_key = self.api_key.clone();
+        let base_url = self.base_url.clone();
+        match self.provider {
+            ApiProvider::OpenAI => Box::new(OpenAiCompatible::new(
+                ApiProvider::OpenAI,
+                base_url,
+                api_key,
+            )),
+            ApiProvider::OpenRouter => Box::new(OpenRouter::new(base_url, api_key)),
+            ApiProvider::Gemini => Box::new(Gemini::new(base_url, api_key)),
+            ApiProvider::Anthropic => Box::new(Anthropic::new(base_url, api_key)),
+            ApiProvider::Custom => Box::new(OpenAiCompatible::new(
+                ApiProvider::Custom,
+                base_url,
+                api_key,
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn build(provider: ApiProvider) -> Box<dyn Provider> {
+        Endpoint::with_default_url(provider, "key".to_string()).build()
+    }
+
+    fn ids(models: Vec<ModelInfo>) -> Vec<String> {
+        models.into_iter().map(|m| m.id).collect()
+    }
+
+    #[test]
+    fn test_openai_compatible_parsing() {
+        let provider = build(ApiProvider::OpenRouter);
+        let chat = json!({ "choices": [{ "message": { "content": "{\"matches\":[]}" } }] });
+        assert_eq!(
+            provider.extract_content(&chat).as_deref(),
+            Some(r#"{"matches":[]}"#)
+        );
+
+        let models = json!({ "data": [{ "id": "a" }, { "id": "b" }, { "name": "no-id" }] });
+        assert_eq!(ids(provider.parse_models(&models)), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn test_gemini_parsing() {
+        let provider = build(ApiProvider::Gemini);
+        let chat = json!({ "candidates": [{ "content": { "parts": [{ "text": "hi" }] } }] });
+        assert_eq!(provider.extract_content(&chat).as_deref(), Some("hi"));
+
+        let models = json!({ "models": [{ "name": "models/gemini-pro" }] });
+        assert_eq!(ids(provider.parse_models(&models)), vec!["gemini-pro"]);
+    }
+
+    #[test]
+    fn test_anthropic_parsing() {
+        let provider = build(ApiProvider::Anthropic);
+        let chat = json!({
+            "content": [
+                { "type": "thinking", "thinking": "..." },
+                { "type": "text", "text": "{\"matches\":" },
+                { "type": "text", "text": "[]}" }
+            ]
+        });
+        assert_eq!(
+            provider.extract_content(&chat).as_deref(),
+            Some(r#"{"matches":[]}"#)
+        );
+
+        let models = json!({ "data": [{ "id": "claude-haiku-4-5", "type": "model" }] });
+        assert_eq!(
+            ids(provider.parse_models(&models)),
+            vec!["claude-haiku-4-5"]
+        );
+    }
+
+    #[test]
+    fn test_usage_and_pricing() {
+        let usage = |prompt_tokens, completion_tokens| Usage {
+            prompt_tokens,
+            completion_tokens,
+        };
+
+        let openai = build(ApiProvider::OpenAI);
+        let body = json!({ "usage": { "prompt_tokens": 120, "completion_tokens": 30 } });
+        assert_eq!(openai.extract_usage(&body), Some(usage(120, 30)));
+        // Streamed chunks before the last one carry `"usage": null`
+        assert_eq!(openai.extract_usage(&json!({ "usage": null })), None);
+
+        let gemini = build(ApiProvider::Gemini);
+        let body = json!({ "usageMetadata": {
+            "promptTokenCount": 100, "candidatesTokenCount": 20, "thoughtsTokenCount": 5
+        } });
+        assert_eq!(gemini.extract_usage(&body), Some(usage(100, 25)));
+
+        let anthropic = build(ApiProvider::Anthropic);
+        let start = json!({ "type": "message_start", "message": {
+            "usage": { "input_tokens": 90, "output_tokens": 1 }
+        } });
+        let delta = json!({ "type": "message_delta", "usage": { "output_tokens": 40 } });
+        let merged = anthropic
+            .extract_usage(&start)
+            .unwrap()
+            .merge(anthropic.extract_usage(&delta).unwrap());
+        assert_eq!(merged, usage(90, 40));
+        assert_eq!(anthropic.extract_usage(&json!({ "type": "ping" })), None);
+
+        let openrouter = build(ApiProvider::OpenRouter);
+        let models = json!({ "data": [
+            { "id": "a", "pricing": { "prompt": "0.000001", "completion": "0.000002" } },
+            { "id": "b" }
+        ] });
+        let models = openrouter.parse_models(&models);
+        assert_eq!(
+            models[0].pricing,
+            Some(ModelPricing {
+                prompt: 0.000001,
+                completion: 0.000002
+            })
+        );
+        assert_eq!(models[1].pricing, None);
+    }
+
+    #[test]
+    fn test_model_metadata() {
+        let openrouter = build(ApiProvider::OpenRouter);
+        let models = json!({ "data": [
+            {
+                "id": "a",
+                "context_length": 128000,
+                "architecture": { "modality": "text->text" },
+                "supported_parameters": ["temperature", "structured_outputs"]
+            },
+            { "id": "b", "supported_parameters": ["temperature"] }
+        ] });
+        let models = openrouter.parse_models(&models);
+        assert_eq!(models[0].context_length, Some(128_000));
+        assert_eq!(models[0].structured_output, Some(true));
+        assert_eq!(models[0].modality.as_deref(), Some("text->text"));
+        assert_eq!(models[1].context_length, None);
+        assert_eq!(models[1].structured_output, Some(false));
+
+        let gemini = build(ApiProvider::Gemini);
+        let models = json!({ "models": [{
+            "name": "models/gemini-2.5-flash",
+            "inputTokenLimit": 1048576,
+            "supportedGenerationMethods": ["generateContent", "countTokens"]
+        }] });
+        let models = gemini.parse_models(&models);
+        assert_eq!(models[0].context_length, Some(1_048_576));
+        assert_eq!(models[0].structured_output, Some(true));
+    }
+
+    #[test]
+    fn test_stream_deltas() {
+        let openai = build(ApiProvider::OpenAI);
+        let event = json!({ "choices": [{ "delta": { "content": "{\"ma" } }] });
+        assert_eq!(
+            openai.extract_stream_delta(&event).as_deref(),
+            Some("{\"ma")
+        );
+        let role_only = json!({ "choices": [{ "delta": { "role": "assistant" } }] });
+        assert_eq!(openai.extract_stream_delta(&role_only), None);
+
+        let anthropic = build(ApiProvider::Anthropic);
+        let event = json!({
+            "type": "content_block_delta",
+            "index": 0,
+            "delta": { "type": "text_delta", "text": "tches" }
+        });
+        assert_eq!(
+            anthropic.extract_stream_delta(&event).as_deref(),
+            Some("tches")
+        );
+        let ping = json!({ "type": "ping" });
+        assert_eq!(anthropic.extract_stream_delta(&ping), None);
+    }
+
+    #[test]
+    fn test_custom_provider_key_is_optional() {
+        let endpoint = Endpoint::new(
+            ApiProvider::Custom,
+            String::new(),
+            "http://localhost:8080/v1/".to_string(),
+        );
+        assert_eq!(endpoint.base_url, "http://localhost:8080/v1");
+        assert!(endpoint.build().has_credentials());
+
+        let openai = Endpoint::with_default_url(ApiProvider::OpenAI, String::new()).build();
+        assert!(!openai.has_credentials());
+    }
+
+    #[test]
+    fn test_gemini_history_and_system_instruction() {
+        let history = [
+            HistoryEntry {
+                role: "user".to_string(),
+                content: "Text:\nI has a cat.".to_string(),
+            },
+            HistoryEntry {
+                role: "assistant".to_string(),
+                content: r#"{"matches":[]}"#.to_string(),
+            },
+        ];
+        let chat = ChatRequest {
+            model: "gemini-2.0-flash",
+            system_prompt: "prompt",
+            history: &history,
+            user_message: "Text:\nShe go home.",
+            stream: false,
+            response_schema: None,
+        };
+        let request = build(ApiProvider::Gemini)
+            .chat_request(&Client::new(), &chat)
+            .build()
+            .unwrap();
+        let body: Value =
+            serde_json::from_slice(request.body().unwrap().as_bytes().unwrap()).unwrap();
+
+        assert_eq!(body["systemInstruction"]["parts"][0]["text"], "prompt");
+        let roles: Vec<&str> = body["contents"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|c| c["role"].as_str().unwrap())
+            .collect();
+        assert_eq!(roles, vec!["user", "model", "user"]);
+        assert_eq!(
+            body["contents"][2]["parts"][0]["text"],
+            "Text:\nShe go home."
+        );
+    }
+
+    #[test]
+    fn test_response_schema_in_requests() {
+        fn body(provider: &dyn Provider, schema: &Value) -> Value {
+            let chat = ChatRequest {
+                model: "model",
+                system_prompt: "prompt",
+                history: &[],
+                user_message: "Text:\nI has a cat.",
+                stream: false,
+                response_schema: Some(schema),
+            };
+            let request = provider
+                .chat_request(&Client::new(), &chat)
+                .build()
+                .unwrap();
+            serde_json::from_slice(request.body().unwrap().as_bytes().unwrap()).unwrap()
+        }
+
+        let schema = crate::suggestion::LlmResponse::json_schema();
+
+        let openai = body(build(ApiProvider::OpenAI).as_ref(), &schema);
+        assert_eq!(openai["response_format"]["type"], "json_schema");
+        assert_eq!(openai["response_format"]["json_schema"]["schema"], schema);
+
+        let gemini = body(build(ApiProvider::Gemini).as_ref(), &schema);
+        let item = &gemini["generationConfig"]["responseSchema"]["properties"]["matches"]["items"];
+        assert_eq!(item["type"], "OBJECT");
+        assert_eq!(item["properties"]["replacement"]["type"], "STRING");
+        assert_eq!(item["properties"]["replacement"]["nullable"], true);
+        assert_eq!(
+            item["properties"]["severity"]["enum"],
+            json!(["error", "warning", "suggestion", "variant"])
+        );
+        assert!(item.get("additionalProperties").is_none());
+    }
+}
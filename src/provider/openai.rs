@@ -0,0 +1,213 @@
+use reqwest::{Client, RequestBuilder};
+use serde_json::{json, Value};
+
+use super::{ChatRequest, ModelInfo, Provider};
+use crate::config::ApiProvider;
+use crate::usage::{ModelPricing, Usage};
+
+/// Any server implementing OpenAI's `/chat/completions` and `/models` endpoints.
+/// Used for OpenAI itself and for self-hosted servers.
+pub struct OpenAiCompatible {
+    kind: ApiProvider,
+    base_url: String,
+    api_key: String,
+}
+
+impl OpenAiCompatible {
+    pub fn new(kind: ApiProvider, base_url: String, api_key: String) -> Self {
+        Self {
+            kind,
+            base_url,
+            api_key,
+        }
+    }
+
+    fn authorize(&self, request: RequestBuilder) -> RequestBuilder {
+        if self.api_key.is_empty() {
+            request
+        } else {
+            request.header("Authorization", format!("Bearer {}", self.api_key))
+        }
+    }
+}
+
+impl Provider for OpenAiCompatible {
+    fn kind(&self) -> ApiProvider {
+        self.kind.clone()
+    }
+
+    fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    fn has_credentials(&self) -> bool {
+        !self.api_key.is_empty() || !self.kind.requires_api_key()
+    }
+
+    fn chat_request(&self, client: &Client, chat: &ChatRequest<'_>) -> RequestBuilder {
+        // Build messages array: system prompt + history + current user message
+        let mut messages = vec![json!({ "role": "system", "content": chat.system_prompt })];
+        for entry in chat.history {
+            messages.push(json!({
+                "role": entry.role,
+                "content": entry.content
+            }));
+        }
+        messages.push(json!({ "role": "user", "content": chat.user_message }));
+
+        let response_format = match chat.response_schema {
+            Some(schema) => json!({
+                "type": "json_schema",
+                "json_schema": {
+                    "name": "grammar_check",
+                    "strict": true,
+                    "schema": schema
+                }
+            }),
+            None => json!({ "type": "json_object" }),
+        };
+
+        let mut body = json!({
+            "messages": messages,
+            "response_format": response_format,
+            "stream": chat.stream
+        });
+        if chat.stream {
+            // The last chunk then carries the token counts
+            body["stream_options"] = json!({ "include_usage": true });
+        }
+        // llama.cpp serves a single model and accepts requests without one.
+        if !chat.model.is_empty() {
+            body["model"] = json!(chat.model);
+        }
+
+        self.authorize(client.post(format!("{}/chat/completions", self.base_url)))
+            .header("Content-Type", "application/json")
+            .json(&body)
+    }
+
+    fn extract_content(&self, body: &Value) -> Option<String> {
+        body["choices"][0]["message"]["content"]
+            .as_str()
+            .map(|s| s.to_string())
+    }
+
+    fn extract_stream_delta(&self, event: &Value) -> Option<String> {
+        event["choices"][0]["delta"]["content"]
+            .as_str()
+            .map(|s| s.to_string())
+    }
+
+    fn extract_usage(&self, body: &Value) -> Option<Usage> {
+        let usage = &body["usage"];
+        Some(Usage {
+            prompt_tokens: usage["prompt_tokens"].as_u64()?,
+            completion_tokens: usage["completion_tokens"].as_u64().unwrap_or(0),
+        })
+    }
+
+    fn models_request(&self, client: &Client) -> RequestBuilder {
+        self.authorize(client.get(format!("{}/models", self.base_url)))
+    }
+
+    fn parse_models(&self, body: &Value) -> Vec<ModelInfo> {
+        body["data"]
+            .as_array()
+            .map(|models| {
+                models
+                    .iter()
+                    .filter_map(|m| m["id"].as_str().map(ModelInfo::new))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    fn health_check_request(&self, client: &Client) -> RequestBuilder {
+        self.models_request(client)
+    }
+}
+
+/// OpenRouter speaks the OpenAI protocol but wants attribution headers and
+/// has a dedicated endpoint for validating keys.
+pub struct OpenRouter {
+    inner: OpenAiCompatible,
+}
+
+impl OpenRouter {
+    pub fn new(base_url: String, api_key: String) -> Self {
+        Self {
+            inner: OpenAiCompatible::new(ApiProvider::OpenRouter, base_url, api_key),
+        }
+    }
+
+    fn attribute(request: RequestBuilder) -> RequestBuilder {
+        request
+            .header("HTTP-Referer", "https://github.com/grammy-app")
+            .header("X-Title", "Grammy")
+    }
+}
+
+impl Provider for OpenRouter {
+    fn kind(&self) -> ApiProvider {
+        ApiProvider::OpenRouter
+    }
+
+    fn base_url(&self) -> &str {
+        self.inner.base_url()
+    }
+
+    fn has_credentials(&self) -> bool {
+        self.inner.has_credentials()
+    }
+
+    fn chat_request(&self, client: &Client, chat: &ChatRequest<'_>) -> RequestBuilder {
+        Self::attribute(self.inner.chat_request(client, chat))
+    }
+
+    fn extract_content(&self, body: &Value) -> Option<String> {
+        self.inner.extract_content(body)
+    }
+
+    fn extract_stream_delta(&self, event: &Value) -> Option<String> {
+        self.inner.extract_stream_delta(event)
+    }
+
+    fn models_request(&self, client: &Client) -> RequestBuilder {
+        self.inner.models_request(client)
+    }
+
+    fn extract_usage(&self, body: &Value) -> Option<Usage> {
+        self.inner.extract_usage(body)
+    }
+
+    fn parse_models(&self, body: &Value) -> Vec<ModelInfo> {
+        // Prices are per token, as decimal strings
+        let price = |m: &Value, field: &str| m["pricing"][field].as_str()?.parse::<f64>().ok();
+
+        body["data"]
+            .as_array()
+            .map(|models| {
+                models
+                    .iter()
+                    .filter_map(|m| {
+                        let mut info = ModelInfo::new(m["id"].as_str()?);
+                        info.pricing = price(m, "prompt")
+                            .zip(price(m, "completion"))
+                            .map(|(prompt, completion)| ModelPricing { prompt, completion });
+                        info.context_length = m["context_length"].as_u64();
+                        info.structured_output = m["supported_parameters"]
+                            .as_array()
+                            .map(|params| params.iter().any(|p| p == "structured_outputs"));
+                        info.modality = m["architecture"]["modality"].as_str().map(String::from);
+                        Some(info)
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    fn health_check_request(&self, client: &Client) -> RequestBuilder {
+        let url = format!("{}/key", self.inner.base_url);
+        Self::attribute(self.inner.authorize(client.get(url)))
+    }
+}
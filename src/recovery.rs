@@ -0,0 +1,164 @@
+//! Salvaging suggestions from model output that is not quite valid JSON:
+//! answers wrapped in ```json fences or prose, trailing commas, and single
+//! broken entries in an otherwise fine `"matches"` array.
+
+use crate::streaming::MatchScanner;
+use crate::suggestion::{LlmMatch, LlmResponse};
+
+/// The matches that could be read, and how many entries had to be given up on.
+#[derive(Debug, Default)]
+pub struct Recovered {
+    pub matches: Vec<LlmMatch>,
+    pub dropped: usize,
+}
+
+/// Parse the model's answer, repairing it as far as possible. Fails only when
+/// no `"matches"` array can be found at all.
+pub fn parse_response(content: &str) -> Result<Recovered, serde_json::Error> {
+    let strict_error = match serde_json::from_str::<LlmResponse>(content) {
+        Ok(response) => {
+            return Ok(Recovered {
+                matches: response.matches,
+                dropped: 0,
+            })
+        }
+        Err(e) => e,
+    };
+
+    let Some(object) = outermost_object(content) else {
+        return Err(strict_error);
+    };
+    let repaired = strip_trailing_commas(object);
+    if let Ok(response) = serde_json::from_str::<LlmResponse>(&repaired) {
+        return Ok(Recovered {
+            matches: response.matches,
+            dropped: 0,
+        });
+    }
+
+    // Take the entries one at a time, so a broken one costs only itself
+    if !repaired.contains("\"matches\"") {
+        return Err(strict_error);
+    }
+    let mut scanner = MatchScanner::default();
+    let matches = scanner.push(&repaired);
+    Ok(Recovered {
+        matches,
+        dropped: scanner.dropped(),
+    })
+}
+
+/// The text from the first `{` to its matching `}`, or to the end if the
+/// object was cut off.
+fn outermost_object(content: &str) -> Option<&str> {
+    let start = content.find('{')?;
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for (i, b) in content.bytes().enumerate().skip(start) {
+        if in_string {
+            if escaped {
+                escaped = false;
+            } else if b == b'\\' {
+                escaped = true;
+            } else if b == b'"' {
+                in_string = false;
+            }
+            continue;
+        }
+        match b {
+            b'"' => in_string = true,
+            b'{' | b'[' => depth += 1,
+            b'}' | b']' => {
+                depth = depth.saturating_sub(1);
+                if depth == 0 {
+                    return Some(&content[start..=i]);
+                }
+            }
+            _ => {}
+        }
+    }
+    Some(&content[start..])
+}
+
+/// Remove commas directly followed by a closing brace or bracket, outside strings.
+pub(crate) fn strip_trailing_commas(json: &str) -> String {
+    let mut out = String::with_capacity(json.len());
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for (i, c) in json.char_indices() {
+        if in_string {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == '"' {
+                in_string = false;
+            }
+        } else if c == '"' {
+            in_string = true;
+        } else if c == ',' {
+            let next = json[i + 1..].trim_start().chars().next();
+            if matches!(next, Some('}') | Some(']')) {
+                continue;
+            }
+        }
+        out.push(c);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fenced_with_prose_and_trailing_commas() {
+        let content = "Here are the corrections:\n```json\n{\"matches\": [\n  {\"message\": \"Agreement\", \"original\": \"has\", \"replacement\": \"have\", \"severity\": \"error\",},\n]}\n```\nLet me know if you need more.";
+        let recovered = parse_response(content).unwrap();
+        assert_eq!(recovered.matches.len(), 1);
+        assert_eq!(recovered.matches[0].original, "has");
+        assert_eq!(recovered.dropped, 0);
+    }
+
+    #[test]
+    fn test_broken_entry_is_dropped_alone() {
+        let content = r#"{"matches": [
+            {"message": "Typo", "original": "teh", "replacement": "the", "severity": "error"},
+            {"message": "Bad severity", "original": "a", "replacement": "an", "severity": "critical"},
+            {"message": "Missing comma" "original": "x", "replacement": null},
+            {"message": "Word choice", "original": "very unique", "replacement": "unique", "severity": "warning"}
+        ]}"#;
+        let recovered = parse_response(content).unwrap();
+        let originals: Vec<&str> = recovered
+            .matches
+            .iter()
+            .map(|m| m.original.as_str())
+            .collect();
+        assert_eq!(originals, vec!["teh", "very unique"]);
+        assert_eq!(recovered.dropped, 2);
+    }
+
+    #[test]
+    fn test_truncated_response_keeps_complete_entries() {
+        let content = r#"{"matches": [{"message": "Typo", "original": "teh", "replacement": "the"}, {"message": "Cut"#;
+        let recovered = parse_response(content).unwrap();
+        assert_eq!(recovered.matches.len(), 1);
+    }
+
+    #[test]
+    fn test_unrecoverable() {
+        assert!(parse_response("I could not find any errors.").is_err());
+        assert!(parse_response(r#"{"result": "fine"}"#).is_err());
+    }
+
+    #[test]
+    fn test_commas_inside_strings_are_kept() {
+        assert_eq!(
+            strip_trailing_commas(r#"{"a": "x,]", "b": [1, 2, ], }"#),
+            r#"{"a": "x,]", "b": [1, 2 ] }"#
+        );
+    }
+}
@@ -0,0 +1,179 @@
+//! Incremental decoding of streamed (SSE) completions.
+//!
+//! [`SseDecoder`] turns raw response chunks into event payloads, and
+//! [`MatchScanner`] picks complete entries out of the `"matches"` array while
+//! the model is still writing the rest of the JSON document.
+
+use crate::recovery::strip_trailing_commas;
+use crate::suggestion::LlmMatch;
+
+/// Splits a `text/event-stream` body into the `data` payload of each event.
+#[derive(Debug, Default)]
+pub struct SseDecoder {
+    buffer: Vec<u8>,
+    data: Vec<String>,
+}
+
+impl SseDecoder {
+    /// Feed a chunk of the response body, returning every event completed by it.
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<String> {
+        self.buffer.extend_from_slice(chunk);
+
+        let mut events = Vec::new();
+        while let Some(newline) = self.buffer.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=newline).collect();
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim_end_matches(['\r', '\n']);
+
+            if line.is_empty() {
+                // A blank line terminates the event
+                if !self.data.is_empty() {
+                    events.push(self.data.join("\n"));
+                    self.data.clear();
+                }
+            } else if let Some(value) = line.strip_prefix("data:") {
+                self.data
+                    .push(value.strip_prefix(' ').unwrap_or(value).to_string());
+            }
+            // `event:`, `id:`, `retry:` and `:` comments carry nothing we need
+        }
+        events
+    }
+
+    /// Flush an event left unterminated when the body ended.
+    pub fn finish(&mut self) -> Option<String> {
+        let rest = std::mem::take(&mut self.buffer);
+        if let Some(value) = String::from_utf8_lossy(&rest)
+            .trim_end()
+            .strip_prefix("data:")
+        {
+            self.data.push(value.trim_start().to_string());
+        }
+        if self.data.is_empty() {
+            None
+        } else {
+            Some(std::mem::take(&mut self.data).join("\n"))
+        }
+    }
+}
+
+/// Extracts each object of the `{"matches": [...]}` array as soon as its
+/// closing brace arrives.
+#[derive(Debug, Default)]
+pub struct MatchScanner {
+    buffer: String,
+    pos: usize,
+    stack: Vec<u8>,
+    in_string: bool,
+    escaped: bool,
+    object_start: Option<usize>,
+    dropped: usize,
+}
+
+impl MatchScanner {
+    /// Append streamed text, returning the matches completed by it.
+    pub fn push(&mut self, delta: &str) -> Vec<LlmMatch> {
+        self.buffer.push_str(delta);
+
+        let mut matches = Vec::new();
+        let bytes = self.buffer.as_bytes();
+        // JSON punctuation is ASCII, so scanning bytes never splits a character we care about.
+        while self.pos < bytes.len() {
+            let b = bytes[self.pos];
+            let at = self.pos;
+            self.pos += 1;
+
+            if self.in_string {
+                if self.escaped {
+                    self.escaped = false;
+                } else if b == b'\\' {
+                    self.escaped = true;
+                } else if b == b'"' {
+                    self.in_string = false;
+                }
+                continue;
+            }
+
+            match b {
+                b'"' if !self.stack.is_empty() => self.in_string = true,
+                b'{' | b'[' => {
+                    // An object directly inside the top-level object's array is one match
+                    if b == b'{' && self.stack == [b'{', b'['] {
+                        self.object_start = Some(at);
+                    }
+                    self.stack.push(b);
+                }
+                b'}' | b']' => {
+                    self.stack.pop();
+                    if b == b'}' && self.stack == [b'{', b'['] {
+                        if let Some(start) = self.object_start.take() {
+                            let raw = strip_trailing_commas(&self.buffer[start..=at]);
+                            match serde_json::from_str::<LlmMatch>(&raw) {
+                                Ok(m) => matches.push(m),
+                                Err(e) => {
+                                    self.dropped += 1;
+                                    eprintln!("[DEBUG] Skipping malformed streamed match: {}", e)
+                                }
+                            }
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        matches
+    }
+
+    /// Entries that closed but could not be read as a match.
+    pub fn dropped(&self) -> usize {
+        self.dropped
+    }
+
+    /// Everything received so far.
+    pub fn text(&self) -> &str {
+        &self.buffer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sse_events_split_across_chunks() {
+        let mut decoder = SseDecoder::default();
+        assert!(decoder.push(b"event: delta\ndata: {\"a\"").is_empty());
+        assert_eq!(
+            decoder.push(b":1}\r\n\r\ndata: [DONE]\n\n"),
+            vec!["{\"a\":1}", "[DONE]"]
+        );
+        assert!(decoder.push(b"data: tail").is_empty());
+        assert_eq!(decoder.finish().as_deref(), Some("tail"));
+    }
+
+    #[test]
+    fn test_matches_emitted_as_they_close() {
+        let mut scanner = MatchScanner::default();
+        let first = r#"{"matches": [{"message": "m", "original": "has {", "#;
+        assert!(scanner.push(first).is_empty());
+
+        let found = scanner.push(r#""replacement": "have", "severity": "error"}, {"mess"#);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].original, "has {");
+
+        let found = scanner.push(r#"age": "x", "original": "y", "replacement": null}]}"#);
+        assert_eq!(found.len(), 1);
+        assert!(found[0].replacement.is_none());
+        assert!(scanner.text().ends_with("]}"));
+    }
+
+    #[test]
+    fn test_malformed_match_is_skipped() {
+        let mut scanner = MatchScanner::default();
+        let found = scanner.push(
+            r#"{"matches": [{"message": 1}, {"message": "ok", "original": "a", "replacement": "b"}]}"#,
+        );
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].message, "ok");
+    }
+}
@@ -0,0 +1,191 @@
+//! A team's shared style guide, kept in a `.grammy.toml` file in the project:
+//!
+//! ```toml
+//! rules = ["Use the Oxford comma.", "Prefer active voice in instructions."]
+//! banned = ["utilize", "very unique"]
+//!
+//! [preferred]
+//! "e-mail" = "email"
+//! "web site" = "website"
+//! ```
+//!
+//! All of it is sent to the model with each check. Banned phrases and
+//! preferred terms are also looked for locally, so they are flagged even
+//! when the model misses them.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::suggestion::{Severity, Suggestion};
+
+pub const FILE_NAME: &str = ".grammy.toml";
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StyleGuide {
+    /// Free-form rules for the model.
+    pub rules: Vec<String>,
+    /// Words and phrases never to use.
+    pub banned: Vec<String>,
+    /// Terms to replace, mapped to what to write instead.
+    pub preferred: BTreeMap<String, String>,
+}
+
+impl StyleGuide {
+    pub fn parse(toml: &str) -> Result<Self, String> {
+        toml::from_str(toml).map_err(|e| e.message().to_string())
+    }
+
+    /// Read the style guide at `path`.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let toml = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        Self::parse(&toml)
+    }
+
+    /// Everything in the guide phrased as rules for the prompt.
+    pub fn prompt_rules(&self) -> Vec<String> {
+        let banned = self
+            .banned
+            .iter()
+            .map(|phrase| format!("Never use \"{}\".", phrase));
+        let preferred = self
+            .preferred
+            .iter()
+            .map(|(avoid, prefer)| format!("Write \"{}\", not \"{}\".", prefer, avoid));
+        self.rules
+            .iter()
+            .cloned()
+            .chain(banned)
+            .chain(preferred)
+            .collect()
+    }
+
+    /// Banned phrases and terms with a preferred spelling found in `text`,
+    /// matched as whole words regardless of ASCII case.
+    pub fn check(&self, text: &str) -> Vec<Suggestion> {
+        let banned = self.banned.iter().flat_map(|phrase| {
+            find_phrase(text, phrase).map(move |offset| {
+                Suggestion::new(
+                    format!("The style guide bans \"{}\"", phrase),
+                    offset,
+                    text[offset..offset + phrase.len()].to_string(),
+                    None,
+                    Severity::Warning,
+                )
+            })
+        });
+        let preferred = self.preferred.iter().flat_map(|(avoid, prefer)| {
+            find_phrase(text, avoid).map(move |offset| {
+                let original = &text[offset..offset + avoid.len()];
+                Suggestion::new(
+                    format!("The style guide prefers \"{}\"", prefer),
+                    offset,
+                    original.to_string(),
+                    Some(match_capital(original, prefer)),
+                    Severity::Warning,
+                )
+            })
+        });
+
+        let mut suggestions: Vec<Suggestion> = banned.chain(preferred).collect();
+        suggestions.sort_by_key(|s| s.offset);
+        let mut last_end = 0;
+        suggestions.retain(|s| {
+            let keep = s.offset >= last_end;
+            if keep {
+                last_end = s.offset + s.length;
+            }
+            keep
+        });
+        suggestions
+    }
+}
+
+/// The nearest `.grammy.toml` in `dir` or one of its parents.
+pub fn discover(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .map(|d| d.join(FILE_NAME))
+        .find(|path| path.is_file())
+}
+
+/// Byte offsets of `phrase` in `text` as a whole word, ignoring ASCII case.
+fn find_phrase<'a>(text: &'a str, phrase: &'a str) -> impl Iterator<Item = usize> + 'a {
+    let is_word_char = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric());
+    text.char_indices().filter_map(move |(i, _)| {
+        let found = !phrase.is_empty()
+            && text
+                .get(i..i + phrase.len())
+                .is_some_and(|s| s.eq_ignore_ascii_case(phrase))
+            && !is_word_char(text[..i].chars().next_back())
+            && !is_word_char(text[i + phrase.len()..].chars().next());
+        found.then_some(i)
+    })
+}
+
+/// `replacement`, capitalised if `original` starts with a capital.
+fn match_capital(original: &str, replacement: &str) -> String {
+    let mut chars = replacement.chars();
+    match (original.chars().next(), chars.next()) {
+        (Some(o), Some(r)) if o.is_uppercase() => r.to_uppercase().chain(chars).collect(),
+        _ => replacement.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn guide() -> StyleGuide {
+        StyleGuide::parse(
+            r#"
+            rules = ["Use the Oxford comma."]
+            banned = ["utilize"]
+
+            [preferred]
+            "e-mail" = "email"
+            "#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_prompt_rules() {
+        assert_eq!(
+            guide().prompt_rules(),
+            vec![
+                "Use the Oxford comma.",
+                "Never use \"utilize\".",
+                "Write \"email\", not \"e-mail\"."
+            ]
+        );
+        assert!(StyleGuide::parse("banned = \"not a list\"").is_err());
+        assert!(StyleGuide::parse("unknown = 1").is_err());
+    }
+
+    #[test]
+    fn test_local_check() {
+        let text = "E-mail us to utilize the e-mails. We utilized it.";
+        let found = guide().check(text);
+        let summary: Vec<(&str, Option<&str>)> = found
+            .iter()
+            .map(|s| (s.original.as_str(), s.replacement.as_deref()))
+            .collect();
+        // "e-mails" and "utilized" are other words
+        assert_eq!(summary, vec![("E-mail", Some("Email")), ("utilize", None)]);
+        assert_eq!(found[1].offset, 13);
+    }
+
+    #[test]
+    fn test_discover_walks_up() {
+        let root = std::env::temp_dir().join(format!("grammy-style-{}", std::process::id()));
+        let nested = root.join("docs").join("guides");
+        std::fs::create_dir_all(&nested).unwrap();
+        std::fs::write(root.join(FILE_NAME), "rules = []").unwrap();
+
+        let found = discover(&nested);
+        std::fs::remove_dir_all(&root).unwrap();
+        assert_eq!(found, Some(root.join(FILE_NAME)));
+    }
+}
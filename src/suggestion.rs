@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
 
 /// Severity level for a suggestion, determines highlighting color.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
@@ -8,6 +9,38 @@ pub enum Severity {
     Error, // Red - grammar errors, typos
     Warning,    // Orange - awkward phrasing
     Suggestion, // Yellow - minor improvements
+    Variant,    // Violet - spelling from another regional variety
+}
+
+impl Severity {
+    pub const ALL: [Severity; 4] = [
+        Severity::Error,
+        Severity::Warning,
+        Severity::Suggestion,
+        Severity::Variant,
+    ];
+
+    /// As written in the model's answer.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Suggestion => "suggestion",
+            Severity::Variant => "variant",
+        }
+    }
+
+    /// What the level is for, as explained to the model.
+    pub fn description(&self) -> &'static str {
+        match self {
+            Severity::Error => "Grammar errors, typos, incorrect word usage",
+            Severity::Warning => "Awkward phrasing, non-native sounding expressions",
+            Severity::Suggestion => "Minor improvements, optional enhancements",
+            Severity::Variant => {
+                "Correct spelling from another regional variety, e.g. color vs colour; never an error"
+            }
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -54,3 +87,36 @@ pub struct LlmMatch {
 pub struct LlmResponse {
     pub matches: Vec<LlmMatch>,
 }
+
+impl LlmResponse {
+    /// JSON Schema for the response, suitable for OpenAI's strict structured outputs.
+    /// Keep in step with the fields of `LlmResponse` and `LlmMatch`.
+    pub fn json_schema() -> Value {
+        let severities: Vec<Value> = Severity::ALL
+            .iter()
+            .filter_map(|s| serde_json::to_value(s).ok())
+            .collect();
+
+        json!({
+            "type": "object",
+            "properties": {
+                "matches": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "message": { "type": "string" },
+                            "original": { "type": "string" },
+                            "replacement": { "type": ["string", "null"] },
+                            "severity": { "type": "string", "enum": severities }
+                        },
+                        "required": ["message", "original", "replacement", "severity"],
+                        "additionalProperties": false
+                    }
+                }
+            },
+            "required": ["matches"],
+            "additionalProperties": false
+        })
+    }
+}
@@ -0,0 +1,405 @@
+//! Token usage and cost accounting: what each request was billed, what the
+//! models cost, and running totals per day, persisted with `confy`.
+
+use std::collections::BTreeMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::ApiProvider;
+
+/// Tokens billed for one request.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Usage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+}
+
+impl Usage {
+    pub fn total(&self) -> u64 {
+        self.prompt_tokens + self.completion_tokens
+    }
+
+    /// Combine two reports about the same response. Streamed responses repeat
+    /// or grow their counts as they go, so the larger figure wins.
+    pub fn merge(self, other: Usage) -> Usage {
+        Usage {
+            prompt_tokens: self.prompt_tokens.max(other.prompt_tokens),
+            completion_tokens: self.completion_tokens.max(other.completion_tokens),
+        }
+    }
+}
+
+/// Price of a model in USD per token.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ModelPricing {
+    pub prompt: f64,
+    pub completion: f64,
+}
+
+impl ModelPricing {
+    pub const FREE: ModelPricing = ModelPricing {
+        prompt: 0.0,
+        completion: 0.0,
+    };
+
+    fn per_million(prompt: f64, completion: f64) -> Self {
+        Self {
+            prompt: prompt / 1_000_000.0,
+            completion: completion / 1_000_000.0,
+        }
+    }
+
+    pub fn cost(&self, usage: &Usage) -> f64 {
+        usage.prompt_tokens as f64 * self.prompt + usage.completion_tokens as f64 * self.completion
+    }
+}
+
+/// List prices in USD per million prompt/completion tokens for providers whose
+/// model listing carries no pricing. Matched by prefix, so dated snapshots
+/// (`claude-haiku-4-5-20251001`) find their family. Estimates only.
+const LIST_PRICES: &[(ApiProvider, &str, f64, f64)] = &[
+    (ApiProvider::OpenAI, "gpt-4o-mini", 0.15, 0.60),
+    (ApiProvider::OpenAI, "gpt-4o", 2.50, 10.00),
+    (ApiProvider::OpenAI, "gpt-4.1-nano", 0.10, 0.40),
+    (ApiProvider::OpenAI, "gpt-4.1-mini", 0.40, 1.60),
+    (ApiProvider::OpenAI, "gpt-4.1", 2.00, 8.00),
+    (ApiProvider::Gemini, "gemini-2.0-flash-lite", 0.075, 0.30),
+    (ApiProvider::Gemini, "gemini-2.0-flash", 0.10, 0.40),
+    (ApiProvider::Gemini, "gemini-2.5-flash-lite", 0.10, 0.40),
+    (ApiProvider::Gemini, "gemini-2.5-flash", 0.30, 2.50),
+    (ApiProvider::Gemini, "gemini-2.5-pro", 1.25, 10.00),
+    (ApiProvider::Anthropic, "claude-haiku-4-5", 1.00, 5.00),
+    (ApiProvider::Anthropic, "claude-3-5-haiku", 0.80, 4.00),
+    (ApiProvider::Anthropic, "claude-sonnet-4", 3.00, 15.00),
+    (ApiProvider::Anthropic, "claude-opus-4", 15.00, 75.00),
+];
+
+/// Built-in price for `model`, if known. Self-hosted models cost nothing per token.
+pub fn list_price(provider: &ApiProvider, model: &str) -> Option<ModelPricing> {
+    if *provider == ApiProvider::Custom {
+        return Some(ModelPricing::FREE);
+    }
+
+    LIST_PRICES
+        .iter()
+        .filter(|(p, prefix, _, _)| p == provider && model.starts_with(prefix))
+        .max_by_key(|(_, prefix, _, _)| prefix.len())
+        .map(|(_, _, prompt, completion)| ModelPricing::per_million(*prompt, *completion))
+}
+
+/// Running totals over some period.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct UsageTotals {
+    pub requests: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    /// Estimated cost in USD of the requests whose price was known.
+    pub cost: f64,
+    /// Requests to models with no known price, left out of `cost`.
+    pub unpriced_requests: u64,
+    /// Tokens of those requests, for budgets to count at a guessed price.
+    #[serde(default)]
+    pub unpriced_tokens: u64,
+}
+
+impl UsageTotals {
+    pub fn record(&mut self, usage: Usage, cost: Option<f64>) {
+        self.requests += 1;
+        self.prompt_tokens += usage.prompt_tokens;
+        self.completion_tokens += usage.completion_tokens;
+        match cost {
+            Some(cost) => self.cost += cost,
+            None => {
+                self.unpriced_requests += 1;
+                self.unpriced_tokens += usage.total();
+            }
+        }
+    }
+
+    pub fn total_tokens(&self) -> u64 {
+        self.prompt_tokens + self.completion_tokens
+    }
+
+    /// Short form for the status bar, e.g. `12.3k tokens · $0.0042`.
+    pub fn summary(&self) -> String {
+        format!(
+            "{} tokens · {}",
+            format_tokens(self.total_tokens()),
+            self.cost_label()
+        )
+    }
+
+    /// The estimated cost, flagging requests it could not include.
+    pub fn cost_label(&self) -> String {
+        let cost = format_cost(self.cost);
+        if self.unpriced_requests > 0 {
+            format!("{} + {} unpriced", cost, self.unpriced_requests)
+        } else {
+            cost
+        }
+    }
+
+    fn add(&mut self, other: &UsageTotals) {
+        self.requests += other.requests;
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+        self.cost += other.cost;
+        self.unpriced_requests += other.unpriced_requests;
+        self.unpriced_tokens += other.unpriced_tokens;
+    }
+}
+
+pub fn format_tokens(tokens: u64) -> String {
+    match tokens {
+        0..=999 => tokens.to_string(),
+        1_000..=999_999 => format!("{:.1}k", tokens as f64 / 1_000.0),
+        _ => format!("{:.2}M", tokens as f64 / 1_000_000.0),
+    }
+}
+
+pub fn format_cost(usd: f64) -> String {
+    if usd > 0.0 && usd < 0.01 {
+        format!("${:.4}", usd)
+    } else {
+        format!("${:.2}", usd)
+    }
+}
+
+/// What budget limits are measured in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum BudgetUnit {
+    /// Estimated cost in US dollars.
+    #[default]
+    Usd,
+    /// Prompt plus output tokens.
+    Tokens,
+}
+
+/// What a token of a model with no known price counts as against a cost
+/// budget: about the dearest listed prompt price, so unknown prices err
+/// toward pausing early rather than running up a bill.
+const UNPRICED_USD_PER_TOKEN: f64 = 15.0 / 1_000_000.0;
+
+/// Caps on automatic checking. A limit of 0 means no limit.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Budget {
+    pub unit: BudgetUnit,
+    pub daily: f64,
+    pub monthly: f64,
+}
+
+impl Budget {
+    /// Why automatic checks should stop, if a limit has been reached.
+    pub fn exceeded(&self, log: &UsageLog) -> Option<String> {
+        let periods = [
+            ("Daily", self.daily, log.today()),
+            ("Monthly", self.monthly, log.this_month()),
+        ];
+        periods.into_iter().find_map(|(period, limit, totals)| {
+            let spent = self.spent(&totals);
+            (limit > 0.0 && spent >= limit).then(|| {
+                let mut reason = format!(
+                    "{} budget of {} reached ({} used)",
+                    period,
+                    self.format(limit),
+                    self.format(spent)
+                );
+                if self.unit == BudgetUnit::Usd && totals.unpriced_requests > 0 {
+                    reason.push_str(&format!(
+                        "; {} request(s) to models with no known price counted at {}/M tokens",
+                        totals.unpriced_requests,
+                        format_cost(UNPRICED_USD_PER_TOKEN * 1_000_000.0)
+                    ));
+                }
+                reason
+            })
+        })
+    }
+
+    fn spent(&self, totals: &UsageTotals) -> f64 {
+        match self.unit {
+            BudgetUnit::Usd => totals.cost + totals.unpriced_tokens as f64 * UNPRICED_USD_PER_TOKEN,
+            BudgetUnit::Tokens => totals.total_tokens() as f64,
+        }
+    }
+
+    fn format(&self, amount: f64) -> String {
+        match self.unit {
+            BudgetUnit::Usd => format_cost(amount),
+            BudgetUnit::Tokens => format!("{} tokens", format_tokens(amount as u64)),
+        }
+    }
+}
+
+/// Usage totals per UTC day, keyed `YYYY-MM-DD`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UsageLog {
+    #[serde(default)]
+    days: BTreeMap<String, UsageTotals>,
+}
+
+impl UsageLog {
+    pub fn load() -> Self {
+        confy::load("grammy", "usage").unwrap_or_default()
+    }
+
+    pub fn save(&self) {
+        let _ = confy::store("grammy", "usage", self.clone());
+    }
+
+    pub fn record(&mut self, usage: Usage, cost: Option<f64>) {
+        self.days.entry(today()).or_default().record(usage, cost);
+    }
+
+    pub fn today(&self) -> UsageTotals {
+        self.days.get(&today()).copied().unwrap_or_default()
+    }
+
+    pub fn this_month(&self) -> UsageTotals {
+        let today = today();
+        self.since(&today[..8])
+    }
+
+    pub fn all_time(&self) -> UsageTotals {
+        self.since("")
+    }
+
+    /// Totals of every day whose key starts with or sorts after `prefix`.
+    fn since(&self, prefix: &str) -> UsageTotals {
+        let mut totals = UsageTotals::default();
+        for (_, day) in self.days.range(prefix.to_string()..) {
+            totals.add(day);
+        }
+        totals
+    }
+}
+
+/// The current UTC date as `YYYY-MM-DD`.
+fn today() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let (year, month, day) = civil_from_days((secs / 86_400) as i64);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// Convert days since 1970-01-01 to a (year, month, day) date.
+/// See Howard Hinnant's `civil_from_days`.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_civil_from_days() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(11_016), (2000, 2, 29));
+        assert_eq!(civil_from_days(20_741), (2026, 10, 15));
+    }
+
+    #[test]
+    fn test_list_price_prefers_longest_prefix() {
+        let mini = list_price(&ApiProvider::OpenAI, "gpt-4o-mini-2024-07-18").unwrap();
+        assert_eq!(mini, ModelPricing::per_million(0.15, 0.60));
+        let full = list_price(&ApiProvider::OpenAI, "gpt-4o").unwrap();
+        assert_eq!(full, ModelPricing::per_million(2.50, 10.00));
+
+        assert_eq!(
+            list_price(&ApiProvider::Custom, "llama-3"),
+            Some(ModelPricing::FREE)
+        );
+        assert_eq!(list_price(&ApiProvider::OpenAI, "o9-ultra"), None);
+    }
+
+    #[test]
+    fn test_totals_by_period() {
+        let mut log = UsageLog::default();
+        let old = UsageTotals {
+            requests: 4,
+            cost: 1.0,
+            ..Default::default()
+        };
+        log.days.insert("2000-01-01".to_string(), old);
+
+        let usage = Usage {
+            prompt_tokens: 1_000_000,
+            completion_tokens: 1_000_000,
+        };
+        let pricing = ModelPricing::per_million(0.15, 0.60);
+        log.record(usage, Some(pricing.cost(&usage)));
+        log.record(usage, None);
+
+        let today = log.today();
+        assert_eq!(today.requests, 2);
+        assert_eq!(today.total_tokens(), 4_000_000);
+        assert!((today.cost - 0.75).abs() < 1e-9);
+        assert_eq!(today.unpriced_requests, 1);
+        assert_eq!(today.unpriced_tokens, 2_000_000);
+
+        assert_eq!(log.this_month().requests, 2);
+        assert_eq!(log.all_time().requests, 6);
+    }
+
+    #[test]
+    fn test_budget_exceeded() {
+        let mut log = UsageLog::default();
+        let usage = Usage {
+            prompt_tokens: 9_000,
+            completion_tokens: 1_000,
+        };
+        log.record(usage, Some(0.50));
+
+        let mut budget = Budget {
+            unit: BudgetUnit::Usd,
+            daily: 0.0,
+            monthly: 0.0,
+        };
+        assert_eq!(budget.exceeded(&log), None);
+
+        budget.daily = 1.0;
+        assert_eq!(budget.exceeded(&log), None);
+        budget.monthly = 0.5;
+        assert_eq!(
+            budget.exceeded(&log).as_deref(),
+            Some("Monthly budget of $0.50 reached ($0.50 used)")
+        );
+
+        // Requests with no known price still count against a cost budget
+        log.record(usage, None);
+        let budget = Budget {
+            unit: BudgetUnit::Usd,
+            daily: 0.6,
+            monthly: 0.0,
+        };
+        assert_eq!(
+            budget.exceeded(&log).as_deref(),
+            Some("Daily budget of $0.60 reached ($0.65 used); 1 request(s) to models with no known price counted at $15.00/M tokens")
+        );
+
+        log = UsageLog::default();
+        log.record(usage, Some(0.50));
+        let budget = Budget {
+            unit: BudgetUnit::Tokens,
+            daily: 10_000.0,
+            monthly: 0.0,
+        };
+        assert_eq!(
+            budget.exceeded(&log).as_deref(),
+            Some("Daily budget of 10.0k tokens reached (10.0k tokens used)")
+        );
+    }
+}
@@ -0,0 +1,535 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use grammy::api::{
+    check_grammar, check_with_fallbacks, fetch_models, test_connection, Backend, CheckOptions,
+    Progress,
+};
+use grammy::app::history::HistoryEntry;
+use grammy::config::ApiProvider;
+use grammy::error::GrammyError;
+use grammy::mock::{MockResponse, MockServer};
+use grammy::network::NetworkSettings;
+use grammy::provider::Endpoint;
+use grammy::retry::RetryPolicy;
+use grammy::suggestion::Suggestion;
+use grammy::usage::Usage;
+
+// These tests run against a local stand-in for the provider APIs and need no
+// network access or API keys. See live_api_test.rs for the real thing.
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+const HAS_A_CAT: &str = r#"{"matches":[{"message":"Subject-verb agreement","original":"has","replacement":"have","severity":"error"}]}"#;
+
+fn options() -> CheckOptions {
+    CheckOptions {
+        retry: RetryPolicy {
+            max_retries: 2,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(10),
+        },
+        ..CheckOptions::default()
+    }
+}
+
+fn endpoint(server: &MockServer, provider: ApiProvider) -> Endpoint {
+    Endpoint::new(provider, "test-key".to_string(), server.url())
+}
+
+async fn check(
+    endpoint: &Endpoint,
+    model: &str,
+    options: &CheckOptions,
+) -> (Result<Vec<Suggestion>, GrammyError>, Vec<Progress>) {
+    let progress = Mutex::new(Vec::new());
+    let result = check_grammar(
+        endpoint.build().as_ref(),
+        "I has a cat.".to_string(),
+        model.to_string(),
+        1,
+        vec![],
+        options,
+        &|p| progress.lock().unwrap().push(p),
+    )
+    .await
+    .map(|(suggestions, _)| suggestions);
+    (result, progress.into_inner().unwrap())
+}
+
+fn usages(progress: &[Progress]) -> Vec<Usage> {
+    progress
+        .iter()
+        .filter_map(|p| match p {
+            Progress::Usage { usage, .. } => Some(*usage),
+            _ => None,
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn test_openai_check() {
+    let server = MockServer::start().await;
+    server.respond("/chat/completions", MockResponse::openai_chat(HAS_A_CAT));
+
+    let endpoint = endpoint(&server, ApiProvider::OpenAI);
+    let (result, progress) = check(&endpoint, "mock-openai", &options()).await;
+    let suggestions = result.unwrap();
+
+    assert_eq!(suggestions.len(), 1);
+    assert_eq!(suggestions[0].offset, 2);
+    assert_eq!(suggestions[0].replacement.as_deref(), Some("have"));
+    assert_eq!(
+        usages(&progress),
+        vec![Usage {
+            prompt_tokens: 100,
+            completion_tokens: 20
+        }]
+    );
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].method, "POST");
+    assert_eq!(requests[0].header("authorization"), Some("Bearer test-key"));
+    let body = requests[0].json();
+    assert_eq!(body["model"], "mock-openai");
+    assert_eq!(body["response_format"]["type"], "json_schema");
+}
+
+#[tokio::test]
+async fn test_openrouter_stream() {
+    let server = MockServer::start().await;
+    let (first, rest) = HAS_A_CAT.split_at(40);
+    server.respond(
+        "/chat/completions",
+        MockResponse::openai_stream(&[first, rest]),
+    );
+
+    let endpoint = endpoint(&server, ApiProvider::OpenRouter);
+    let options = CheckOptions {
+        stream: true,
+        ..options()
+    };
+    let (result, progress) = check(&endpoint, "mock/stream", &options).await;
+    let suggestions = result.unwrap();
+
+    assert_eq!(suggestions.len(), 1);
+    let partial: Vec<&Suggestion> = progress
+        .iter()
+        .filter_map(|p| match p {
+            Progress::Partial(s) => Some(s),
+            _ => None,
+        })
+        .flatten()
+        .collect();
+    assert_eq!(partial.len(), 1);
+    assert_eq!(partial[0].id, suggestions[0].id);
+    assert_eq!(usages(&progress).len(), 1);
+    assert_eq!(server.requests()[0].json()["stream"], true);
+}
+
+#[tokio::test]
+async fn test_gemini_check() {
+    let server = MockServer::start().await;
+    server.respond(
+        "/models/mock-gemini:generateContent",
+        MockResponse::gemini_chat(HAS_A_CAT),
+    );
+
+    let endpoint = endpoint(&server, ApiProvider::Gemini);
+    let (result, progress) = check(&endpoint, "mock-gemini", &options()).await;
+
+    assert_eq!(result.unwrap().len(), 1);
+    assert_eq!(usages(&progress).len(), 1);
+    let request = &server.requests()[0];
+    assert!(request.path.ends_with("key=test-key"));
+    assert_eq!(
+        request.json()["generationConfig"]["responseSchema"]["type"],
+        "OBJECT"
+    );
+}
+
+#[tokio::test]
+async fn test_rate_limit_is_retried() {
+    let server = MockServer::start().await;
+    server.respond(
+        "/chat/completions",
+        MockResponse::error(429, "Slow down").header("Retry-After", "0"),
+    );
+    server.respond("/chat/completions", MockResponse::openai_chat(HAS_A_CAT));
+
+    let endpoint = endpoint(&server, ApiProvider::OpenAI);
+    let (result, progress) = check(&endpoint, "mock-openai", &options()).await;
+
+    assert_eq!(result.unwrap().len(), 1);
+    assert!(progress
+        .iter()
+        .any(|p| matches!(p, Progress::Retrying { attempt: 1, .. })));
+    assert_eq!(server.requests().len(), 2);
+}
+
+#[tokio::test]
+async fn test_rate_limit_gives_up() {
+    let server = MockServer::start().await;
+    for _ in 0..3 {
+        server.respond(
+            "/chat/completions",
+            MockResponse::error(429, "Slow down").header("Retry-After", "0"),
+        );
+    }
+
+    let endpoint = endpoint(&server, ApiProvider::OpenAI);
+    let (result, _) = check(&endpoint, "mock-openai", &options()).await;
+
+    assert!(matches!(
+        result,
+        Err(GrammyError::RateLimited { ref message, .. }) if message == "Slow down"
+    ));
+    assert_eq!(server.requests().len(), 3);
+}
+
+#[tokio::test]
+async fn test_unauthorized_needs_settings() {
+    let server = MockServer::start().await;
+    server.respond(
+        "/chat/completions",
+        MockResponse::error(401, "Invalid API key"),
+    );
+
+    let endpoint = endpoint(&server, ApiProvider::OpenAI);
+    let (result, _) = check(&endpoint, "mock-openai", &options()).await;
+
+    let error = result.unwrap_err();
+    assert!(error.needs_settings());
+    assert_eq!(server.requests().len(), 1, "401 must not be retried");
+}
+
+#[tokio::test]
+async fn test_malformed_json_is_recovered() {
+    let server = MockServer::start().await;
+    let fenced = format!("Sure! Here you go:\n```json\n{}\n```", HAS_A_CAT);
+    server.respond("/chat/completions", MockResponse::openai_chat(&fenced));
+
+    let endpoint = endpoint(&server, ApiProvider::OpenAI);
+    let (result, _) = check(&endpoint, "mock-openai", &options()).await;
+
+    assert_eq!(result.unwrap().len(), 1);
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn test_invalid_json_asks_for_a_fix() {
+    let server = MockServer::start().await;
+    server.respond(
+        "/chat/completions",
+        MockResponse::openai_chat("There is one error: 'has' should be 'have'."),
+    );
+    server.respond("/chat/completions", MockResponse::openai_chat(HAS_A_CAT));
+
+    let endpoint = endpoint(&server, ApiProvider::OpenAI);
+    let options = CheckOptions {
+        fix_invalid_json: true,
+        ..options()
+    };
+    let (result, progress) = check(&endpoint, "mock-openai", &options).await;
+
+    assert_eq!(result.unwrap().len(), 1);
+    assert_eq!(usages(&progress).len(), 2);
+    let follow_up = server.requests()[1].json();
+    let messages = follow_up["messages"].as_array().unwrap();
+    assert_eq!(messages[messages.len() - 2]["role"], "assistant");
+}
+
+#[tokio::test]
+async fn test_invalid_json_without_fix() {
+    let server = MockServer::start().await;
+    server.respond(
+        "/chat/completions",
+        MockResponse::openai_chat("No JSON here."),
+    );
+
+    let endpoint = endpoint(&server, ApiProvider::OpenAI);
+    let (result, _) = check(&endpoint, "mock-openai", &options()).await;
+
+    assert!(matches!(result, Err(GrammyError::InvalidLlmJson(_))));
+}
+
+#[tokio::test]
+async fn test_rejected_schema_falls_back_to_json_mode() {
+    let server = MockServer::start().await;
+    server.respond(
+        "/chat/completions",
+        MockResponse::error(400, "response_format json_schema is not supported"),
+    );
+    server.respond("/chat/completions", MockResponse::openai_chat(HAS_A_CAT));
+
+    let endpoint = endpoint(&server, ApiProvider::Custom);
+    let (result, _) = check(&endpoint, "mock-no-schema", &options()).await;
+
+    assert_eq!(result.unwrap().len(), 1);
+    let requests = server.requests();
+    assert_eq!(requests[0].json()["response_format"]["type"], "json_schema");
+    assert_eq!(requests[1].json()["response_format"]["type"], "json_object");
+}
+
+#[tokio::test]
+async fn test_openrouter_connection() {
+    let server = MockServer::start().await;
+    let models = serde_json::json!({ "data": [{ "id": "mock/a" }, { "id": "mock/b" }] });
+    server.respond(
+        "/key",
+        MockResponse::json(200, serde_json::json!({ "data": {} })),
+    );
+    server.respond("/models", MockResponse::json(200, models.clone()));
+    server.respond(
+        "/key",
+        MockResponse::json(200, serde_json::json!({ "data": {} })),
+    );
+    server.respond("/models", MockResponse::json(200, models));
+
+    let provider = endpoint(&server, ApiProvider::OpenRouter).build();
+    let client = reqwest::Client::new();
+    assert_eq!(
+        test_connection(provider.as_ref(), &client, "mock/b".to_string(), TIMEOUT, 7).await,
+        Ok(7)
+    );
+    assert_eq!(
+        test_connection(
+            provider.as_ref(),
+            &client,
+            "mock/missing".to_string(),
+            TIMEOUT,
+            8
+        )
+        .await,
+        Err(GrammyError::ModelNotFound {
+            provider: ApiProvider::OpenRouter,
+            model: "mock/missing".to_string()
+        })
+    );
+}
+
+#[tokio::test]
+async fn test_connection_with_bad_key() {
+    let server = MockServer::start().await;
+    server.respond(
+        "/key",
+        MockResponse::error(401, "No auth credentials found"),
+    );
+
+    let provider = endpoint(&server, ApiProvider::OpenRouter).build();
+    let client = reqwest::Client::new();
+    let error = test_connection(provider.as_ref(), &client, "mock/a".to_string(), TIMEOUT, 1)
+        .await
+        .unwrap_err();
+    assert!(error.needs_settings());
+}
+
+#[tokio::test]
+async fn test_gemini_models() {
+    let server = MockServer::start().await;
+    server.respond(
+        "/models",
+        MockResponse::json(
+            200,
+            serde_json::json!({ "models": [
+                { "name": "models/gemini-b" },
+                { "name": "models/gemini-a" }
+            ] }),
+        ),
+    );
+
+    let provider = endpoint(&server, ApiProvider::Gemini).build();
+    let client = reqwest::Client::new();
+    let models = fetch_models(provider.as_ref(), &client, TIMEOUT, 1)
+        .await
+        .unwrap();
+    let ids: Vec<&str> = models.iter().map(|m| m.id.as_str()).collect();
+    assert_eq!(ids, vec!["gemini-a", "gemini-b"]);
+}
+
+#[tokio::test]
+async fn test_requests_go_through_the_proxy() {
+    let proxy = MockServer::start().await;
+    proxy.respond(
+        "/models",
+        MockResponse::json(200, serde_json::json!({ "data": [{ "id": "a" }] })),
+    );
+
+    let network = NetworkSettings {
+        http_proxy: proxy.url(),
+        proxy_username: "me".to_string(),
+        proxy_password: "secret".to_string(),
+        ..NetworkSettings::default()
+    };
+    let client = network.client().unwrap();
+    // Never resolved: the proxy answers in its place
+    let provider = Endpoint::new(
+        ApiProvider::Custom,
+        String::new(),
+        "http://llm.invalid/v1".to_string(),
+    )
+    .build();
+    let models = fetch_models(provider.as_ref(), &client, TIMEOUT, 1)
+        .await
+        .unwrap();
+
+    assert_eq!(models.len(), 1);
+    let request = &proxy.requests()[0];
+    assert_eq!(request.path, "http://llm.invalid/v1/models");
+    // base64("me:secret")
+    assert_eq!(
+        request.header("proxy-authorization"),
+        Some("Basic bWU6c2VjcmV0")
+    );
+}
+
+#[tokio::test]
+async fn test_slow_response_times_out() {
+    let server = MockServer::start().await;
+    server.respond(
+        "/chat/completions",
+        MockResponse::openai_chat(HAS_A_CAT).delay(Duration::from_secs(5)),
+    );
+
+    let endpoint = endpoint(&server, ApiProvider::OpenAI);
+    let options = CheckOptions {
+        timeout: Duration::from_millis(200),
+        ..options()
+    };
+    let (result, progress) = check(&endpoint, "mock-openai", &options).await;
+
+    assert!(matches!(result, Err(GrammyError::Timeout)));
+    // Waiting out the timeout again would only delay falling back
+    assert_eq!(server.requests().len(), 1);
+    assert!(!progress
+        .iter()
+        .any(|p| matches!(p, Progress::Retrying { .. })));
+}
+
+fn backend(server: &MockServer, provider: ApiProvider, model: &str) -> Backend {
+    Backend {
+        provider: Arc::from(endpoint(server, provider).build()),
+        model: model.to_string(),
+        context_window: None,
+    }
+}
+
+#[tokio::test]
+async fn test_falls_back_when_the_provider_is_down() {
+    let primary = MockServer::start().await;
+    for _ in 0..3 {
+        primary.respond(
+            "/chat/completions",
+            MockResponse::error(503, "Service unavailable"),
+        );
+    }
+    let backup = MockServer::start().await;
+    backup.respond("/chat/completions", MockResponse::openai_chat(HAS_A_CAT));
+
+    let backends = [
+        backend(&primary, ApiProvider::OpenRouter, "mock/primary"),
+        backend(&backup, ApiProvider::OpenAI, "mock-backup"),
+    ];
+    let progress = Mutex::new(Vec::new());
+    let (suggestions, used) = check_with_fallbacks(
+        &backends,
+        "I has a cat.".to_string(),
+        1,
+        vec![],
+        &options(),
+        &|p| progress.lock().unwrap().push(p),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(suggestions.len(), 1);
+    assert_eq!(used, 1);
+    assert_eq!(primary.requests().len(), 3);
+    let progress = progress.lock().unwrap();
+    assert!(progress.iter().any(|p| matches!(
+        p,
+        Progress::FallingBack { from, to, .. }
+            if from == "OpenRouter · mock/primary" && to == "OpenAI · mock-backup"
+    )));
+    // Usage is billed to the backend that answered
+    assert!(progress.iter().any(|p| matches!(
+        p,
+        Progress::Usage { provider: ApiProvider::OpenAI, model, .. } if model == "mock-backup"
+    )));
+}
+
+#[tokio::test]
+async fn test_settings_errors_do_not_fall_back() {
+    let primary = MockServer::start().await;
+    primary.respond(
+        "/chat/completions",
+        MockResponse::error(401, "Invalid API key"),
+    );
+    let backup = MockServer::start().await;
+
+    let backends = [
+        backend(&primary, ApiProvider::OpenAI, "mock-primary"),
+        backend(&backup, ApiProvider::OpenAI, "mock-backup"),
+    ];
+    let result = check_with_fallbacks(
+        &backends,
+        "I has a cat.".to_string(),
+        1,
+        vec![],
+        &options(),
+        &|_| {},
+    )
+    .await;
+
+    assert!(result.unwrap_err().needs_settings());
+    assert!(backup.requests().is_empty());
+}
+
+#[tokio::test]
+async fn test_oversized_text_is_checked_in_chunks() {
+    let server = MockServer::start().await;
+    let teh = r#"{"matches":[{"message":"Typo","original":"teh","replacement":"the","severity":"error"}]}"#;
+    for _ in 0..3 {
+        server.respond("/chat/completions", MockResponse::openai_chat(teh));
+    }
+
+    let paragraph = format!("I saw teh cat. {}\n\n", "It sat on the mat. ".repeat(30));
+    let text = paragraph.repeat(3);
+    let old_entry = |role: &str| HistoryEntry {
+        role: role.to_string(),
+        content: format!("Text:\n{}", "An earlier check. ".repeat(100)),
+    };
+    let options = CheckOptions {
+        context_window: Some(1_000),
+        ..options()
+    };
+    let (suggestions, _) = check_grammar(
+        endpoint(&server, ApiProvider::OpenAI).build().as_ref(),
+        text.clone(),
+        "mock-small".to_string(),
+        1,
+        vec![old_entry("user"), old_entry("assistant")],
+        &options,
+        &|_| {},
+    )
+    .await
+    .unwrap();
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 3);
+    // The history no longer fits next to a chunk and is left out
+    assert!(requests
+        .iter()
+        .all(|r| r.json()["messages"].as_array().unwrap().len() == 2));
+
+    let mut offsets: Vec<usize> = suggestions.iter().map(|s| s.offset).collect();
+    offsets.sort();
+    assert_eq!(
+        offsets,
+        vec![6, paragraph.len() + 6, 2 * paragraph.len() + 6]
+    );
+    assert!(suggestions
+        .iter()
+        .all(|s| &text[s.offset..s.offset + s.length] == "teh"));
+}